@@ -0,0 +1,46 @@
+CREATE TABLE public.guild_settings
+(
+    guild_id         bigint primary key,
+    event_channel_id bigint
+);
+
+CREATE TABLE public.guild_auto_reactions
+(
+    guild_id bigint NOT NULL,
+    keyword  text   NOT NULL,
+    emoji    text   NOT NULL,
+    primary key (guild_id, keyword)
+);
+
+CREATE TABLE public.guild_auto_replies
+(
+    id          serial primary key,
+    guild_id    bigint           NOT NULL,
+    keywords    text[]           NOT NULL,
+    user_id     bigint           NOT NULL,
+    title       text             NOT NULL,
+    description text             NOT NULL,
+    ping        boolean          NOT NULL DEFAULT false,
+    colour      integer          NOT NULL DEFAULT 0,
+    chance      double precision
+);
+
+CREATE INDEX ON public.guild_auto_replies (guild_id);
+
+CREATE TABLE public.guild_link_fixes
+(
+    guild_id bigint NOT NULL,
+    domain   text   NOT NULL,
+    host     text,
+    tracking text,
+    primary key (guild_id, domain)
+);
+
+CREATE TABLE public.guild_celery
+(
+    channel_id bigint primary key,
+    guild_id   bigint           NOT NULL,
+    prompt     text             NOT NULL,
+    chance     double precision NOT NULL,
+    cooldown   bigint           NOT NULL
+);
@@ -0,0 +1,3 @@
+-- 0 = substring, 1 = whole word, 2 = regex
+ALTER TABLE public.guild_auto_replies
+    ADD COLUMN match_mode smallint NOT NULL DEFAULT 0;
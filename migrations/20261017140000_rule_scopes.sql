@@ -0,0 +1,14 @@
+-- cooldowns are in seconds, empty allowed_channels means every channel
+ALTER TABLE public.guild_auto_replies
+    ADD COLUMN channel_cooldown integer,
+    ADD COLUMN user_cooldown    integer,
+    ADD COLUMN allowed_channels bigint[] NOT NULL DEFAULT '{}',
+    ADD COLUMN denied_channels  bigint[] NOT NULL DEFAULT '{}',
+    ADD COLUMN exempt_roles     bigint[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE public.guild_auto_reactions
+    ADD COLUMN channel_cooldown integer,
+    ADD COLUMN user_cooldown    integer,
+    ADD COLUMN allowed_channels bigint[] NOT NULL DEFAULT '{}',
+    ADD COLUMN denied_channels  bigint[] NOT NULL DEFAULT '{}',
+    ADD COLUMN exempt_roles     bigint[] NOT NULL DEFAULT '{}';
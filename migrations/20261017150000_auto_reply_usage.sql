@@ -0,0 +1,17 @@
+CREATE TABLE public.auto_reply_usage
+(
+    day     date   NOT NULL,
+    keyword text   NOT NULL,
+    user_id bigint NOT NULL,
+    count   bigint NOT NULL,
+    primary key (keyword, user_id, day)
+);
+
+CREATE INDEX ON public.auto_reply_usage (day);
+
+-- the old totals have no date, they are kept on 1970-01-01 so they only count for all time
+INSERT INTO public.auto_reply_usage (day, keyword, user_id, count)
+SELECT '1970-01-01', keyword, user_id, count
+FROM public.auto_replies;
+
+DROP TABLE public.auto_replies;
@@ -0,0 +1,9 @@
+CREATE TABLE public.celery_facts
+(
+    id         serial primary key,
+    channel_id bigint      NOT NULL,
+    fact       text        NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON public.celery_facts (channel_id, created_at);
@@ -0,0 +1,19 @@
+CREATE TABLE public.llm_threads
+(
+    thread_id  bigint primary key,
+    guild_id   bigint      NOT NULL,
+    user_id    bigint      NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE public.llm_messages
+(
+    id         serial primary key,
+    thread_id  bigint      NOT NULL REFERENCES public.llm_threads ON DELETE CASCADE,
+    -- system, user or assistant
+    role       text        NOT NULL,
+    content    text        NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON public.llm_messages (thread_id);
@@ -0,0 +1,14 @@
+ALTER TABLE public.guild_settings
+    ADD COLUMN auto_link_fix    boolean NOT NULL DEFAULT false,
+    ADD COLUMN link_fix_webhook boolean NOT NULL DEFAULT false;
+
+CREATE TABLE public.guild_link_fix_opt_outs
+(
+    channel_id bigint primary key,
+    guild_id   bigint NOT NULL
+);
+
+CREATE TABLE public.link_fix_user_opt_outs
+(
+    user_id bigint primary key
+);
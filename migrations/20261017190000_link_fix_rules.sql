@@ -0,0 +1,6 @@
+ALTER TABLE public.guild_link_fixes
+    ALTER COLUMN tracking TYPE text[] USING CASE WHEN tracking IS NULL THEN '{}' ELSE ARRAY [tracking] END,
+    ALTER COLUMN tracking SET DEFAULT '{}',
+    ALTER COLUMN tracking SET NOT NULL,
+    ADD COLUMN path_patterns     text[] NOT NULL DEFAULT '{}',
+    ADD COLUMN path_replacements text[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,22 @@
+CREATE TABLE public.activity_daily
+(
+    guild_id    bigint  NOT NULL,
+    user_id     bigint  NOT NULL,
+    channel_id  bigint  NOT NULL,
+    day         date    NOT NULL,
+    messages    integer NOT NULL DEFAULT 0,
+    reactions   integer NOT NULL DEFAULT 0,
+    voice_joins integer NOT NULL DEFAULT 0,
+    primary key (guild_id, user_id, channel_id, day)
+);
+
+CREATE INDEX ON public.activity_daily (guild_id, day);
+
+-- lifetime counts have no day or channel, they count as 1970-01-01 in channel 0
+INSERT INTO public.activity_daily (guild_id, user_id, channel_id, day, messages)
+SELECT guild_id, user_id, 0, '1970-01-01', message_count
+FROM public.activity
+WHERE message_count > 0;
+
+ALTER TABLE public.activity
+    DROP COLUMN message_count;
@@ -0,0 +1,14 @@
+CREATE TABLE public.voice_sessions
+(
+    id         serial primary key,
+    guild_id   bigint      NOT NULL,
+    user_id    bigint      NOT NULL,
+    channel_id bigint      NOT NULL,
+    joined_at  timestamptz NOT NULL DEFAULT now(),
+    -- refreshed while the bot runs, ends sessions that were open when it stopped
+    last_seen  timestamptz NOT NULL DEFAULT now(),
+    left_at    timestamptz
+);
+
+CREATE INDEX ON public.voice_sessions (guild_id, joined_at);
+CREATE UNIQUE INDEX ON public.voice_sessions (guild_id, user_id) WHERE left_at IS NULL;
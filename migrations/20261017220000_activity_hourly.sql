@@ -0,0 +1,10 @@
+CREATE TABLE public.activity_hourly
+(
+    guild_id bigint      NOT NULL,
+    user_id  bigint      NOT NULL,
+    hour     timestamptz NOT NULL,
+    count    integer     NOT NULL DEFAULT 0,
+    primary key (guild_id, user_id, hour)
+);
+
+CREATE INDEX ON public.activity_hourly (guild_id, hour);
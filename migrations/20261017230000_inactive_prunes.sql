@@ -0,0 +1,24 @@
+CREATE TABLE public.inactive_prunes
+(
+    id          serial primary key,
+    guild_id    bigint      NOT NULL,
+    -- the summary is sent here
+    channel_id  bigint      NOT NULL,
+    action      smallint    NOT NULL,
+    role_id     bigint,
+    warned_at   timestamptz NOT NULL DEFAULT now(),
+    deadline    timestamptz NOT NULL,
+    finished_at timestamptz
+);
+
+CREATE UNIQUE INDEX ON public.inactive_prunes (guild_id) WHERE finished_at IS NULL;
+
+CREATE TABLE public.inactive_prune_members
+(
+    prune_id integer  NOT NULL REFERENCES public.inactive_prunes ON DELETE CASCADE,
+    user_id  bigint   NOT NULL,
+    dm_sent  boolean  NOT NULL DEFAULT false,
+    -- set when the grace period is over
+    outcome  smallint,
+    primary key (prune_id, user_id)
+);
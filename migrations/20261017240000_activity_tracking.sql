@@ -0,0 +1,11 @@
+-- guilds from active_guilds in the config are added as enabled on startup
+CREATE TABLE public.activity_tracking
+(
+    guild_id bigint primary key,
+    enabled  boolean NOT NULL
+);
+
+CREATE TABLE public.activity_opt_outs
+(
+    user_id bigint primary key
+);
@@ -0,0 +1,7 @@
+-- emojis in messages are counted separately from reactions
+ALTER TABLE public.emoji_usage
+    RENAME COLUMN count TO reactions;
+
+ALTER TABLE public.emoji_usage
+    ALTER COLUMN reactions SET DEFAULT 0,
+    ADD COLUMN messages bigint NOT NULL DEFAULT 0;
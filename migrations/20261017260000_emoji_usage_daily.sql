@@ -0,0 +1,21 @@
+CREATE TABLE public.emoji_usage_daily
+(
+    guild_id  bigint  NOT NULL,
+    user_id   bigint  NOT NULL,
+    emoji     text    NOT NULL,
+    day       date    NOT NULL,
+    reactions integer NOT NULL DEFAULT 0,
+    messages  integer NOT NULL DEFAULT 0,
+    primary key (guild_id, emoji, day, user_id)
+);
+
+CREATE INDEX ON public.emoji_usage_daily (guild_id, day);
+
+-- lifetime counts have no day or user, they count as 1970-01-01 by user 0
+INSERT INTO public.emoji_usage_daily (guild_id, user_id, emoji, day, reactions, messages)
+SELECT guild_id, 0, emoji, '1970-01-01', GREATEST(reactions, 0), messages
+FROM public.emoji_usage
+WHERE reactions > 0
+   OR messages > 0;
+
+DROP TABLE public.emoji_usage;
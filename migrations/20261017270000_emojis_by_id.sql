@@ -0,0 +1,65 @@
+-- custom emojis are stored by id so renaming them keeps their statistics,
+-- Unicode emojis stay text and the name is looked up when showing them
+ALTER TABLE public.emoji_usage_daily
+    ADD COLUMN emoji_id bigint,
+    ADD COLUMN unicode  text;
+
+UPDATE public.emoji_usage_daily
+SET emoji_id = substring(emoji FROM '^<a?:\w*:(\d+)>$')::bigint;
+
+UPDATE public.emoji_usage_daily
+SET unicode = emoji
+WHERE emoji_id IS NULL;
+
+-- a renamed emoji has a row per name, the one with the smallest name gets the sums
+UPDATE public.emoji_usage_daily u
+SET reactions = merged.reactions,
+    messages  = merged.messages
+FROM (SELECT guild_id, user_id, emoji_id, day, MIN(emoji) AS emoji, SUM(reactions) AS reactions, SUM(messages) AS messages
+      FROM public.emoji_usage_daily
+      WHERE emoji_id IS NOT NULL
+      GROUP BY guild_id, user_id, emoji_id, day
+      HAVING COUNT(*) > 1) merged
+WHERE u.guild_id = merged.guild_id
+  AND u.user_id = merged.user_id
+  AND u.day = merged.day
+  AND u.emoji = merged.emoji;
+
+DELETE
+FROM public.emoji_usage_daily u USING public.emoji_usage_daily keep
+WHERE u.guild_id = keep.guild_id
+  AND u.user_id = keep.user_id
+  AND u.day = keep.day
+  AND u.emoji_id = keep.emoji_id
+  AND u.emoji > keep.emoji;
+
+ALTER TABLE public.emoji_usage_daily
+    DROP CONSTRAINT emoji_usage_daily_pkey,
+    DROP COLUMN emoji,
+    ADD CHECK ((emoji_id IS NULL) <> (unicode IS NULL)),
+    ADD UNIQUE NULLS NOT DISTINCT (guild_id, emoji_id, unicode, day, user_id);
+
+
+-- the same emoji under several names keeps the reaction role with the smallest name
+ALTER TABLE public.reaction_roles
+    ADD COLUMN emoji_id bigint,
+    ADD COLUMN unicode  text;
+
+UPDATE public.reaction_roles
+SET emoji_id = substring(emoji FROM '^<a?:\w*:(\d+)>$')::bigint;
+
+UPDATE public.reaction_roles
+SET unicode = emoji
+WHERE emoji_id IS NULL;
+
+DELETE
+FROM public.reaction_roles r USING public.reaction_roles keep
+WHERE r.message_id = keep.message_id
+  AND r.emoji_id = keep.emoji_id
+  AND r.emoji > keep.emoji;
+
+ALTER TABLE public.reaction_roles
+    DROP CONSTRAINT reaction_roles_pkey,
+    DROP COLUMN emoji,
+    ADD CHECK ((emoji_id IS NULL) <> (unicode IS NULL)),
+    ADD UNIQUE NULLS NOT DISTINCT (message_id, emoji_id, unicode);
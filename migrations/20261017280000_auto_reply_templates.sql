@@ -0,0 +1,26 @@
+-- titles and descriptions are templates now, braces that weren't placeholders before stay literal
+UPDATE public.guild_auto_replies
+SET title       = replace(replace(title, '{', '{{'), '}', '}}'),
+    description = regexp_replace(replace(replace(description, '{', '{{'), '}', '}}'),
+                                 '\{\{(user|replies|\d+)\}\}', '{\1}', 'g');
+
+-- named regex groups were placeholders in the description too
+DO
+$$
+    DECLARE
+        reply record;
+        name  text;
+    BEGIN
+        FOR reply IN SELECT id, keywords FROM public.guild_auto_replies WHERE match_mode = 2
+            LOOP
+                FOR name IN SELECT DISTINCT m[1]
+                            FROM unnest(reply.keywords) AS keyword,
+                                 regexp_matches(keyword, '\(\?P?<(\w+)>', 'g') AS m
+                    LOOP
+                        UPDATE public.guild_auto_replies
+                        SET description = replace(description, '{{' || name || '}}', '{' || name || '}')
+                        WHERE id = reply.id;
+                    END LOOP;
+            END LOOP;
+    END
+$$;
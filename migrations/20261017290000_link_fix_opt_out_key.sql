@@ -0,0 +1,4 @@
+-- a channel id of another guild must not block the opt-out of this one
+ALTER TABLE public.guild_link_fix_opt_outs
+    DROP CONSTRAINT guild_link_fix_opt_outs_pkey,
+    ADD PRIMARY KEY (guild_id, channel_id);
@@ -0,0 +1,16 @@
+-- usage is counted per guild, older rows go to the only guild with a reply for the keyword
+ALTER TABLE public.auto_reply_usage
+    ADD COLUMN guild_id bigint NOT NULL DEFAULT 0;
+
+UPDATE public.auto_reply_usage u
+SET guild_id = r.guild_id
+FROM (SELECT keywords[1] AS keyword, MIN(guild_id) AS guild_id
+      FROM public.guild_auto_replies
+      GROUP BY keywords[1]
+      HAVING COUNT(DISTINCT guild_id) = 1) r
+WHERE u.keyword = r.keyword;
+
+ALTER TABLE public.auto_reply_usage
+    ALTER COLUMN guild_id DROP DEFAULT,
+    DROP CONSTRAINT auto_reply_usage_pkey,
+    ADD PRIMARY KEY (guild_id, keyword, user_id, day);
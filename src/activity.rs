@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, DurationRound, NaiveDate, TimeDelta, Utc};
+use itertools::Itertools;
+use mini_moka::sync::{Cache, CacheBuilder};
+use poise::serenity_prelude::{ChannelId, Guild, GuildId, UserId};
+use sqlx::{PgPool, query};
+use tokio::time::interval;
+use tracing::{debug, error, info};
+
+use crate::commands::ActivityKind;
+use crate::commands::utils::today;
+use crate::constants::ONE_DAY;
+use crate::{CacheEntry, Data};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const VOICE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+type CountKey = (GuildId, UserId, ChannelId, NaiveDate);
+type HourKey = (GuildId, UserId, DateTime<Utc>);
+
+#[derive(Debug, Default)]
+struct Pending {
+    /// messages, reactions and voice joins
+    counts: HashMap<CountKey, [i32; 3]>,
+    /// everything per hour for the heatmap
+    hourly: HashMap<HourKey, i32>,
+    last_seen: HashMap<(GuildId, UserId), NaiveDate>,
+}
+
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.counts.is_empty() && self.hourly.is_empty() && self.last_seen.is_empty()
+    }
+
+    fn rows(&self) -> usize {
+        self.counts.len() + self.hourly.len() + self.last_seen.len()
+    }
+
+    fn merge(&mut self, other: Pending) {
+        for (key, counts) in other.counts {
+            let entry = self.counts.entry(key).or_default();
+            for (total, count) in entry.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+        for (key, count) in other.hourly {
+            *self.hourly.entry(key).or_default() += count;
+        }
+        for (key, day) in other.last_seen {
+            let entry = self.last_seen.entry(key).or_insert(day);
+            *entry = (*entry).max(day);
+        }
+    }
+}
+
+/// Collects activity in memory, written to the database in batches
+#[derive(Debug, Default)]
+pub(crate) struct ActivityAggregator {
+    pending: Mutex<Pending>,
+    flushes: AtomicU64,
+    flushed_rows: AtomicU64,
+    last_rows: AtomicU64,
+    last_latency_ms: AtomicU64,
+    max_latency_ms: AtomicU64,
+}
+
+impl ActivityAggregator {
+    fn count(&self, key: CountKey, hour: DateTime<Utc>, kind: ActivityKind) {
+        let mut pending = self.pending.lock().expect("pending activity");
+        pending.counts.entry(key).or_default()[kind as usize] += 1;
+        *pending.hourly.entry((key.0, key.1, hour)).or_default() += 1;
+    }
+
+    fn seen(&self, guild: GuildId, user: UserId, day: NaiveDate) {
+        let mut pending = self.pending.lock().expect("pending activity");
+        pending.last_seen.insert((guild, user), day);
+    }
+
+    /// Write everything collected so far, kept for the next flush if that fails
+    pub(crate) async fn flush(&self, database: &PgPool) -> anyhow::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("pending activity"));
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let rows = pending.rows() as u64;
+        let start = Instant::now();
+        if let Err(e) = write(database, &pending).await {
+            self.pending
+                .lock()
+                .expect("pending activity")
+                .merge(pending);
+            return Err(e);
+        }
+        let latency = start.elapsed().as_millis() as u64;
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.flushed_rows.fetch_add(rows, Ordering::Relaxed);
+        self.last_rows.store(rows, Ordering::Relaxed);
+        self.last_latency_ms.store(latency, Ordering::Relaxed);
+        self.max_latency_ms.fetch_max(latency, Ordering::Relaxed);
+        debug!(rows, latency_ms = latency, "Flushed activity");
+        Ok(())
+    }
+
+    /// Flush statistics for the owner
+    pub(crate) fn metrics(&self) -> String {
+        format!(
+            "{} activity flushes with {} rows, the last one {} rows in {}ms, at most {}ms",
+            self.flushes.load(Ordering::Relaxed),
+            self.flushed_rows.load(Ordering::Relaxed),
+            self.last_rows.load(Ordering::Relaxed),
+            self.last_latency_ms.load(Ordering::Relaxed),
+            self.max_latency_ms.load(Ordering::Relaxed)
+        )
+    }
+}
+
+async fn write(database: &PgPool, pending: &Pending) -> anyhow::Result<()> {
+    let (keys, counts): (Vec<&CountKey>, Vec<&[i32; 3]>) = pending.counts.iter().unzip();
+    let (hours, hourly): (Vec<&HourKey>, Vec<i32>) = pending.hourly.iter().unzip();
+    let (seen, days): (Vec<&(GuildId, UserId)>, Vec<NaiveDate>) = pending.last_seen.iter().unzip();
+    let mut transaction = database.begin().await?;
+    query!(
+        "INSERT INTO activity_daily (guild_id, user_id, channel_id, day, messages, reactions, voice_joins)
+         SELECT * FROM UNNEST($1::bigint[], $2::bigint[], $3::bigint[], $4::date[], $5::int[], $6::int[], $7::int[])
+         ON CONFLICT (guild_id, user_id, channel_id, day) DO UPDATE SET
+         messages = activity_daily.messages + excluded.messages,
+         reactions = activity_daily.reactions + excluded.reactions,
+         voice_joins = activity_daily.voice_joins + excluded.voice_joins",
+        &keys.iter().map(|k| k.0.get() as i64).collect_vec(),
+        &keys.iter().map(|k| k.1.get() as i64).collect_vec(),
+        &keys.iter().map(|k| k.2.get() as i64).collect_vec(),
+        &keys.iter().map(|k| k.3).collect_vec(),
+        &counts.iter().map(|c| c[0]).collect_vec(),
+        &counts.iter().map(|c| c[1]).collect_vec(),
+        &counts.iter().map(|c| c[2]).collect_vec()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    query!(
+        "INSERT INTO activity_hourly (guild_id, user_id, hour, count)
+         SELECT * FROM UNNEST($1::bigint[], $2::bigint[], $3::timestamptz[], $4::int[])
+         ON CONFLICT (guild_id, user_id, hour) DO UPDATE SET count = activity_hourly.count + excluded.count",
+        &hours.iter().map(|h| h.0.get() as i64).collect_vec(),
+        &hours.iter().map(|h| h.1.get() as i64).collect_vec(),
+        &hours.iter().map(|h| h.2).collect_vec(),
+        &hourly
+    )
+    .execute(&mut *transaction)
+    .await?;
+    query!(
+        "INSERT INTO activity (guild_id, user_id, last_active)
+         SELECT * FROM UNNEST($1::bigint[], $2::bigint[], $3::date[])
+         ON CONFLICT (user_id, guild_id) DO UPDATE SET
+         last_active = GREATEST(activity.last_active, excluded.last_active)",
+        &seen.iter().map(|s| s.0.get() as i64).collect_vec(),
+        &seen.iter().map(|s| s.1.get() as i64).collect_vec(),
+        &days
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub(crate) fn flush_activity(database: PgPool, aggregator: Arc<ActivityAggregator>) {
+    tokio::spawn(async move {
+        let mut interval = interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = aggregator.flush(&database).await {
+                error!(error = ?err, "Failed flushing activity");
+            }
+        }
+    });
+    info!("Started activity flush thread");
+}
+
+/// Debounces the activity of a guild's members to once per day
+pub(crate) fn activity_cache() -> Cache<UserId, CacheEntry> {
+    CacheBuilder::new(500).time_to_live(ONE_DAY).build()
+}
+
+/// Guilds with activity tracking, those in `active_guilds` of the config
+/// until it is turned off with `/activity_tracking`
+pub(crate) async fn load_tracked_guilds(
+    database: &PgPool,
+    defaults: &[GuildId],
+) -> anyhow::Result<Vec<GuildId>> {
+    let defaults = defaults.iter().map(|g| g.get() as i64).collect_vec();
+    query!(
+        "INSERT INTO activity_tracking (guild_id, enabled) SELECT *, true FROM UNNEST($1::bigint[])
+         ON CONFLICT DO NOTHING",
+        &defaults
+    )
+    .execute(database)
+    .await?;
+    Ok(
+        query!("SELECT guild_id FROM activity_tracking WHERE enabled")
+            .fetch_all(database)
+            .await?
+            .into_iter()
+            .map(|row| GuildId::new(row.guild_id as u64))
+            .collect(),
+    )
+}
+
+pub(crate) async fn load_activity_opt_outs(database: &PgPool) -> anyhow::Result<Vec<UserId>> {
+    Ok(query!("SELECT user_id FROM activity_opt_outs")
+        .fetch_all(database)
+        .await?
+        .into_iter()
+        .map(|row| UserId::new(row.user_id as u64))
+        .collect())
+}
+
+pub(crate) fn is_opted_out(data: &Data, user: UserId) -> bool {
+    data.activity_opt_outs
+        .read()
+        .expect("activity_opt_outs")
+        .contains(&user)
+}
+
+/// The guild's debounce cache, if its activity is tracked
+pub(crate) fn guild_activity(data: &Data, guild: GuildId) -> Option<Cache<UserId, CacheEntry>> {
+    data.activity_per_guild
+        .read()
+        .expect("activity_per_guild")
+        .get(&guild)
+        .cloned()
+}
+
+/// Close the user's open voice session and start one if they are in a channel now
+pub(crate) async fn track_voice(
+    data: &Data,
+    guild: GuildId,
+    user: UserId,
+    channel: Option<ChannelId>,
+) -> anyhow::Result<()> {
+    if guild_activity(data, guild).is_none() || is_opted_out(data, user) {
+        return Ok(());
+    }
+    let mut transaction = data.database.begin().await?;
+    query!(
+        "UPDATE voice_sessions SET left_at = now(), last_seen = now()
+         WHERE guild_id = $1 AND user_id = $2 AND left_at IS NULL",
+        guild.get() as i64,
+        user.get() as i64
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if let Some(channel) = channel {
+        query!(
+            "INSERT INTO voice_sessions (guild_id, user_id, channel_id) VALUES ($1, $2, $3)",
+            guild.get() as i64,
+            user.get() as i64,
+            channel.get() as i64
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Match the sessions to who is in voice when the guild becomes available,
+/// changes while the bot was disconnected were missed
+pub(crate) async fn sync_voice_sessions(data: &Data, guild: &Guild) -> anyhow::Result<()> {
+    if guild_activity(data, guild.id).is_none() {
+        return Ok(());
+    }
+    let (users, channels): (Vec<i64>, Vec<i64>) = guild
+        .voice_states
+        .values()
+        .filter(|state| state.member.as_ref().is_none_or(|m| !m.user.bot))
+        .filter(|state| !is_opted_out(data, state.user_id))
+        .filter_map(|state| Some((state.user_id.get() as i64, state.channel_id?.get() as i64)))
+        .unzip();
+    let mut transaction = data.database.begin().await?;
+    query!(
+        "UPDATE voice_sessions SET left_at = last_seen
+         WHERE guild_id = $1 AND left_at IS NULL AND user_id <> ALL($2)",
+        guild.id.get() as i64,
+        &users
+    )
+    .execute(&mut *transaction)
+    .await?;
+    query!(
+        "INSERT INTO voice_sessions (guild_id, user_id, channel_id)
+         SELECT $1, * FROM UNNEST($2::bigint[], $3::bigint[])
+         ON CONFLICT (guild_id, user_id) WHERE left_at IS NULL DO NOTHING",
+        guild.id.get() as i64,
+        &users,
+        &channels
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Mark the open sessions as still going
+async fn voice_heartbeat(database: &PgPool) -> anyhow::Result<()> {
+    query!("UPDATE voice_sessions SET last_seen = now() WHERE left_at IS NULL")
+        .execute(database)
+        .await?;
+    Ok(())
+}
+
+/// End the sessions that were open when the bot stopped at the last heartbeat
+pub(crate) async fn close_voice_sessions(database: &PgPool) -> anyhow::Result<()> {
+    let closed = query!("UPDATE voice_sessions SET left_at = last_seen WHERE left_at IS NULL")
+        .execute(database)
+        .await?;
+    info!("Closed {} voice sessions", closed.rows_affected());
+    Ok(())
+}
+
+/// Closes the sessions of a shutdown at the current time
+pub(crate) async fn end_voice_sessions(database: &PgPool) -> anyhow::Result<()> {
+    voice_heartbeat(database).await?;
+    close_voice_sessions(database).await
+}
+
+pub(crate) fn keep_voice_sessions(database: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = interval(VOICE_HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = voice_heartbeat(&database).await {
+                error!(error = ?err, "Failed updating voice sessions");
+            }
+        }
+    });
+    info!("Started voice session thread");
+}
+
+/// Count the activity for today and mark the user as active, once per day
+pub(crate) fn update_activity(
+    data: &Data,
+    guild: GuildId,
+    channel: ChannelId,
+    user: UserId,
+    kind: ActivityKind,
+) {
+    let Some(guild_activity) = guild_activity(data, guild) else {
+        return;
+    };
+    if is_opted_out(data, user) {
+        return;
+    }
+    let day = today();
+    let hour = Utc::now()
+        .duration_trunc(TimeDelta::hours(1))
+        .expect("an hour fits");
+    data.activity.count((guild, user, channel, day), hour, kind);
+    if guild_activity.get(&user).is_none() {
+        data.activity.seen(guild, user, day);
+        guild_activity.insert(user, CacheEntry {});
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_pending() {
+        let aggregator = ActivityAggregator::default();
+        let key = (
+            GuildId::new(1),
+            UserId::new(2),
+            ChannelId::new(3),
+            NaiveDate::from_ymd_opt(2026, 10, 17).unwrap(),
+        );
+        let hour = DateTime::from_timestamp(3600, 0).unwrap();
+        aggregator.count(key, hour, ActivityKind::Message);
+        aggregator.count(key, hour, ActivityKind::Message);
+        aggregator.count(key, hour, ActivityKind::VoiceJoin);
+        let failed = std::mem::take(&mut *aggregator.pending.lock().unwrap());
+        aggregator.count(key, hour, ActivityKind::Reaction);
+        aggregator.pending.lock().unwrap().merge(failed);
+
+        let pending = aggregator.pending.lock().unwrap();
+        assert_eq!(pending.counts[&key], [2, 1, 1]);
+        assert_eq!(pending.hourly[&(key.0, key.1, hour)], 4);
+        assert_eq!(pending.rows(), 2);
+    }
+}
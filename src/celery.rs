@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+
+use itertools::Itertools;
+use poise::serenity_prelude::{ChannelId, Context};
+use rand::random_bool;
+use regex::Regex;
+use sqlx::{PgPool, query};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::Data;
+use crate::guild_settings::GuildSettings;
+
+/// How many of the latest facts the prompt asks not to repeat
+const FACTS_IN_PROMPT: i64 = 20;
+/// How many of the latest facts new ones are compared against
+const FACTS_COMPARED: i64 = 500;
+const MAX_ATTEMPTS: usize = 3;
+/// Share of common words from which two facts count as the same
+const SIMILARITY_THRESHOLD: f64 = 0.6;
+
+static WORD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\w+").unwrap());
+
+/// Cooldown and lock of a channel, they have to outlive the cached guild settings
+#[derive(Debug, Default)]
+pub(crate) struct CeleryState {
+    counter: AtomicU64,
+    mutex: Mutex<()>,
+}
+
+fn channel_state(data: &Data, channel: ChannelId) -> Arc<CeleryState> {
+    data.celery_state
+        .write()
+        .expect("celery_state")
+        .entry(channel)
+        .or_default()
+        .clone()
+}
+
+pub(crate) async fn celery_fact(
+    ctx: &Context,
+    data: &Data,
+    settings: &GuildSettings,
+    channel: ChannelId,
+) -> anyhow::Result<()> {
+    if let Some(config) = settings.celery.get(&channel) {
+        let state = channel_state(data, channel);
+        // saturating subtraction
+        let previous_value =
+            state
+                .counter
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val| {
+                    if val == 0 { Some(0) } else { Some(val - 1) }
+                });
+        // not 0 now, still in cooldown
+        if previous_value.unwrap() > 1 {
+            return Ok(());
+        }
+        // unlucky
+        if !random_bool(config.chance) {
+            return Ok(());
+        }
+        // other thread was faster
+        let lock = state.mutex.try_lock();
+        if lock.is_err() {
+            return Ok(());
+        }
+        // reset cooldown and check if we waited so long another thread is done
+        if state
+            .counter
+            .compare_exchange(0, config.cooldown, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Ok(());
+        }
+
+        let Some(fact) = new_fact(data, channel, &config.prompt).await? else {
+            return Ok(());
+        };
+        channel.say(&ctx.http, &fact).await?;
+        query!(
+            "INSERT INTO celery_facts (channel_id, fact) VALUES ($1, $2)",
+            channel.get() as i64,
+            fact
+        )
+        .execute(&data.database)
+        .await?;
+        drop(lock);
+    }
+
+    Ok(())
+}
+
+/// Generate a fact unlike the previous ones of the channel, `None` if the LLM fails or keeps repeating itself
+async fn new_fact(data: &Data, channel: ChannelId, prompt: &str) -> anyhow::Result<Option<String>> {
+    let previous = previous_facts(&data.database, channel, FACTS_COMPARED).await?;
+    let prompt = if previous.is_empty() {
+        prompt.to_string()
+    } else {
+        let recent = previous
+            .iter()
+            .take(FACTS_IN_PROMPT as usize)
+            .map(|fact| format!("- {fact}"))
+            .join("\n");
+        format!("{prompt}\n\nDon't repeat any of these facts:\n{recent}")
+    };
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        // failures are logged by the client and retried after the next cooldown
+        let Ok(fact) = data.llm.generate(&prompt).await else {
+            return Ok(None);
+        };
+        match previous
+            .iter()
+            .find(|old| similarity(old, &fact) >= SIMILARITY_THRESHOLD)
+        {
+            Some(old) => info!(attempt, fact, old, "Rejected repeated celery fact"),
+            None => return Ok(Some(fact)),
+        }
+    }
+    Ok(None)
+}
+
+/// Latest facts first
+async fn previous_facts(
+    database: &PgPool,
+    channel: ChannelId,
+    limit: i64,
+) -> anyhow::Result<Vec<String>> {
+    Ok(query!(
+        "SELECT fact FROM celery_facts WHERE channel_id = $1 ORDER BY created_at DESC LIMIT $2",
+        channel.get() as i64,
+        limit
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| row.fact)
+    .collect())
+}
+
+fn words(text: &str) -> HashSet<String> {
+    WORD_REGEX
+        .find_iter(&text.to_lowercase())
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+/// Jaccard similarity of the lowercase words, 1 for the same words in any order
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        let fact = "Celery is 95% water.";
+        assert_eq!(similarity(fact, "celery is 95% WATER!"), 1.0);
+        assert!(similarity(fact, "Fun fact: celery is 95% water") >= SIMILARITY_THRESHOLD);
+        assert!(
+            similarity(fact, "Celery was used as a hangover cure in ancient Rome")
+                < SIMILARITY_THRESHOLD
+        );
+    }
+}
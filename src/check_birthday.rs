@@ -1,19 +1,16 @@
 use crate::constants::{ONE_DAY, TIMEZONE};
+use crate::guild_settings::get_guild_settings;
 use chrono::{Duration, Utc};
 use itertools::Itertools;
-use poise::serenity_prelude::{ChannelId, Context, GuildId, Mentionable, UserId};
+use poise::serenity_prelude::{Context, GuildId, Mentionable, UserId};
 use sqlx::{PgPool, query};
 use std::collections::HashMap;
 use tokio::time::{Instant, interval_at};
 use tracing::{Level, debug, error, info, span, trace, warn};
 
-pub(crate) fn check_birthdays(
-    ctx: Context,
-    database: PgPool,
-    event_channel: HashMap<GuildId, ChannelId>,
-) {
+pub(crate) fn check_birthdays(ctx: Context, database: PgPool) {
     tokio::spawn(async move {
-        if let Err(err) = send_birthdays(&ctx, &database, &event_channel).await {
+        if let Err(err) = send_birthdays(&ctx, &database).await {
             error!(error = ?err, "Failed checking birthdays");
         }
 
@@ -29,7 +26,7 @@ pub(crate) fn check_birthdays(
         loop {
             interval.tick().await;
 
-            if let Err(err) = send_birthdays(&ctx, &database, &event_channel).await {
+            if let Err(err) = send_birthdays(&ctx, &database).await {
                 error!(error = ?err, "Failed checking birthdays");
             }
         }
@@ -37,11 +34,7 @@ pub(crate) fn check_birthdays(
     info!("Started birthday thread");
 }
 
-async fn send_birthdays(
-    ctx: &Context,
-    database: &PgPool,
-    event_channel: &HashMap<GuildId, ChannelId>,
-) -> anyhow::Result<()> {
+async fn send_birthdays(ctx: &Context, database: &PgPool) -> anyhow::Result<()> {
     let _ = span!(Level::DEBUG, "Sending birthday wishes").enter();
     let local_now = Utc::now().with_timezone(&TIMEZONE);
     let due = query!(
@@ -80,7 +73,8 @@ async fn send_birthdays(
             mentions
         );
 
-        if let Some(channel) = event_channel.get(&guild_id) {
+        let settings = get_guild_settings(database, guild_id).await?;
+        if let Some(channel) = settings.event_channel {
             if let Err(e) = channel.say(ctx, message).await {
                 error!(error = ?e, guild = ?guild_id, users = ?users, "Failed to send birthday message");
             } else {
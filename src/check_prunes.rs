@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use itertools::Itertools;
+use poise::serenity_prelude::{
+    ChannelId, Context, Error as SerenityError, GuildId, HttpError, RoleId, StatusCode, UserId,
+};
+use sqlx::{PgPool, query};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::commands::{PruneAction, PruneOutcome};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Act on the members who stayed inactive once the grace period of `/prune_inactive` is over
+pub(crate) fn check_prunes(ctx: Context, database: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = interval(CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = finish_prunes(&ctx, &database).await {
+                error!(error = ?err, "Failed pruning inactive members");
+            }
+        }
+    });
+    info!("Started prune thread");
+}
+
+async fn finish_prunes(ctx: &Context, database: &PgPool) -> anyhow::Result<()> {
+    let due = query!(
+        r#"SELECT id, guild_id, channel_id, action, role_id, warned_at, EXISTS (
+               SELECT 1 FROM activity_tracking t WHERE t.guild_id = p.guild_id AND t.enabled
+           ) AS "tracked!"
+           FROM inactive_prunes p WHERE finished_at IS NULL AND deadline <= now()"#
+    )
+    .fetch_all(database)
+    .await?;
+
+    for prune in due {
+        let guild = GuildId::new(prune.guild_id as u64);
+        let action = PruneAction::from(prune.action);
+        let role = prune.role_id.map(|id| RoleId::new(id as u64));
+        if !prune.tracked {
+            // without recorded activity everyone would look inactive
+            query!(
+                "UPDATE inactive_prunes SET finished_at = now() WHERE id = $1",
+                prune.id
+            )
+            .execute(database)
+            .await?;
+            ChannelId::new(prune.channel_id as u64)
+                .say(
+                    ctx,
+                    "The grace period for inactive members is over, but activity isn't recorded \
+                     on this server anymore, so nobody was pruned",
+                )
+                .await?;
+            warn!(?guild, "Skipped prune without activity tracking");
+            continue;
+        }
+        // outcomes are saved one by one, a restart continues with the rest
+        let members = query!(
+            r#"SELECT m.user_id, EXISTS (
+                   SELECT 1 FROM activity_hourly h
+                   WHERE h.guild_id = $2 AND h.user_id = m.user_id AND h.hour >= date_trunc('hour', $3::timestamptz)
+               ) AS "active!"
+               FROM inactive_prune_members m WHERE m.prune_id = $1 AND m.outcome IS NULL"#,
+            prune.id,
+            prune.guild_id,
+            prune.warned_at
+        )
+        .fetch_all(database)
+        .await?;
+        for member in members {
+            let user = UserId::new(member.user_id as u64);
+            let outcome = if member.active {
+                PruneOutcome::Active
+            } else {
+                prune_member(ctx, guild, user, action, role).await
+            };
+            query!(
+                "UPDATE inactive_prune_members SET outcome = $3 WHERE prune_id = $1 AND user_id = $2",
+                prune.id,
+                member.user_id,
+                outcome as i16
+            )
+            .execute(database)
+            .await?;
+        }
+
+        query!(
+            "UPDATE inactive_prunes SET finished_at = now() WHERE id = $1",
+            prune.id
+        )
+        .execute(database)
+        .await?;
+        let outcomes = query!(
+            r#"SELECT outcome AS "outcome!", COUNT(*) AS "count!" FROM inactive_prune_members
+               WHERE prune_id = $1 GROUP BY outcome ORDER BY outcome"#,
+            prune.id
+        )
+        .fetch_all(database)
+        .await?
+        .into_iter()
+        .map(|row| (PruneOutcome::from(row.outcome), row.count))
+        .collect_vec();
+        let summary = format!(
+            "The grace period for inactive members is over: {}",
+            summarize(&outcomes)
+        );
+        ChannelId::new(prune.channel_id as u64)
+            .say(ctx, summary)
+            .await?;
+        info!(?guild, ?outcomes, "Pruned inactive members");
+    }
+    Ok(())
+}
+
+async fn prune_member(
+    ctx: &Context,
+    guild: GuildId,
+    user: UserId,
+    action: PruneAction,
+    role: Option<RoleId>,
+) -> PruneOutcome {
+    let member = match guild.member(ctx, user).await {
+        Ok(member) => member,
+        Err(SerenityError::Http(HttpError::UnsuccessfulRequest(response)))
+            if response.status_code == StatusCode::NOT_FOUND =>
+        {
+            return PruneOutcome::Left;
+        }
+        Err(e) => {
+            warn!(?user, error = ?e, "Failed to get inactive member");
+            return PruneOutcome::Failed;
+        }
+    };
+    let result = match (action, role) {
+        (PruneAction::Role, Some(role)) => member
+            .add_role(ctx, role)
+            .await
+            .map(|_| PruneOutcome::RoleAssigned),
+        (PruneAction::Role, None) => return PruneOutcome::Failed,
+        (PruneAction::Kick, _) => member
+            .kick_with_reason(ctx, "Inactive")
+            .await
+            .map(|_| PruneOutcome::Kicked),
+    };
+    result.unwrap_or_else(|e| {
+        warn!(?user, error = ?e, "Failed to prune inactive member");
+        PruneOutcome::Failed
+    })
+}
+
+/// Like `3 kicked, 2 active again`
+fn summarize(outcomes: &[(PruneOutcome, i64)]) -> String {
+    if outcomes.is_empty() {
+        return "nobody was left to prune".to_string();
+    }
+    outcomes
+        .iter()
+        .map(|(outcome, count)| format!("{count} {}", outcome.describe()))
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let outcomes = [(PruneOutcome::Active, 2), (PruneOutcome::Kicked, 3)];
+        assert_eq!(summarize(&outcomes), "2 active again, 3 kicked");
+        assert_eq!(summarize(&[]), "nobody was left to prune");
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use ab_glyph::Font;
+use chrono::NaiveDate;
+use image::imageops::{FilterType, overlay};
+use image::{DynamicImage, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+
+use crate::constants::{DATE_FORMAT, FONT, WHITE};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 400;
+const MARGIN: u32 = 40;
+const FONT_SIZE: f32 = 14.0;
+const BACKGROUND: Rgba<u8> = Rgba([49, 51, 56, 255]);
+const BLURPLE: Rgba<u8> = Rgba([88, 101, 242, 255]);
+
+/// Bar chart with one bar per day from `first` to `last`, missing days are 0
+pub(crate) fn daily_chart(
+    first: NaiveDate,
+    last: NaiveDate,
+    counts: &HashMap<NaiveDate, i64>,
+) -> DynamicImage {
+    let days: Vec<_> = first.iter_days().take_while(|day| *day <= last).collect();
+    let max = counts.values().copied().max().unwrap_or_default().max(1);
+    let mut img = RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+
+    let plot_height = (HEIGHT - 2 * MARGIN) as f32;
+    let bar_width = (WIDTH - 2 * MARGIN) as f32 / days.len().max(1) as f32;
+    for (i, day) in days.iter().enumerate() {
+        let count = counts.get(day).copied().unwrap_or_default();
+        let height = (count as f32 / max as f32 * plot_height).round() as u32;
+        if height == 0 {
+            continue;
+        }
+        let x = MARGIN as f32 + i as f32 * bar_width;
+        // leave a gap between wide bars
+        let width = if bar_width > 4.0 {
+            bar_width * 0.8
+        } else {
+            bar_width
+        };
+        let rect = Rect::at(x as i32, (HEIGHT - MARGIN - height) as i32)
+            .of_size(width.max(1.0) as u32, height);
+        draw_filled_rect_mut(&mut img, rect, BLURPLE);
+    }
+
+    let baseline = (HEIGHT - MARGIN) as f32;
+    draw_line_segment_mut(
+        &mut img,
+        (MARGIN as f32, baseline),
+        ((WIDTH - MARGIN) as f32, baseline),
+        WHITE,
+    );
+
+    let scale = FONT.pt_to_px_scale(FONT_SIZE).unwrap();
+    let label_y = (HEIGHT - MARGIN + 8) as i32;
+    let first_label = first.format(DATE_FORMAT).to_string();
+    draw_text_mut(
+        &mut img,
+        WHITE,
+        MARGIN as i32,
+        label_y,
+        scale,
+        &*FONT,
+        &first_label,
+    );
+    let last_label = last.format(DATE_FORMAT).to_string();
+    let (last_width, _) = text_size(scale, &*FONT, &last_label);
+    let last_x = (WIDTH - MARGIN).saturating_sub(last_width) as i32;
+    draw_text_mut(&mut img, WHITE, last_x, label_y, scale, &*FONT, &last_label);
+    let max_label = format!("max {max}");
+    draw_text_mut(
+        &mut img,
+        WHITE,
+        MARGIN as i32,
+        12,
+        scale,
+        &*FONT,
+        &max_label,
+    );
+
+    DynamicImage::ImageRgba8(img)
+}
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const CELL: u32 = 28;
+const LABEL_WIDTH: u32 = 50;
+const TITLE_HEIGHT: u32 = 36;
+const EMPTY_CELL: Rgba<u8> = Rgba([64, 66, 73, 255]);
+
+/// Grid with a row per weekday from Monday and a column per hour, brighter for more activity
+pub(crate) fn heatmap(title: &str, counts: &[[i64; 24]; 7]) -> DynamicImage {
+    let width = LABEL_WIDTH + 24 * CELL + MARGIN / 2;
+    let height = TITLE_HEIGHT + 7 * CELL + MARGIN;
+    let max = counts
+        .iter()
+        .flatten()
+        .copied()
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    let mut img = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    for (day, hours) in counts.iter().enumerate() {
+        for (hour, &count) in hours.iter().enumerate() {
+            let colour = if count == 0 {
+                EMPTY_CELL
+            } else {
+                blend(EMPTY_CELL, BLURPLE, count as f32 / max as f32)
+            };
+            let rect = Rect::at(
+                (LABEL_WIDTH + hour as u32 * CELL) as i32,
+                (TITLE_HEIGHT + day as u32 * CELL) as i32,
+            )
+            .of_size(CELL - 2, CELL - 2);
+            draw_filled_rect_mut(&mut img, rect, colour);
+        }
+    }
+
+    let scale = FONT.pt_to_px_scale(FONT_SIZE).unwrap();
+    draw_text_mut(&mut img, WHITE, 8, 10, scale, &*FONT, title);
+    for (day, name) in WEEKDAYS.iter().enumerate() {
+        let y = (TITLE_HEIGHT + day as u32 * CELL + 6) as i32;
+        draw_text_mut(&mut img, WHITE, 8, y, scale, &*FONT, name);
+    }
+    let label_y = (TITLE_HEIGHT + 7 * CELL + 6) as i32;
+    for hour in (0..24).step_by(3) {
+        let x = (LABEL_WIDTH + hour * CELL) as i32;
+        draw_text_mut(
+            &mut img,
+            WHITE,
+            x,
+            label_y,
+            scale,
+            &*FONT,
+            &hour.to_string(),
+        );
+    }
+
+    DynamicImage::ImageRgba8(img)
+}
+
+const ROW: u32 = 32;
+const ICON: u32 = 24;
+/// Room for the count behind the longest bar
+const COUNT_WIDTH: u32 = 60;
+
+/// A horizontal bar per emoji with its image in front and the count behind,
+/// emojis without an image only get the bar
+pub(crate) fn emoji_chart(title: &str, bars: &[(Option<DynamicImage>, i64)]) -> DynamicImage {
+    let height = TITLE_HEIGHT + bars.len() as u32 * ROW + MARGIN / 2;
+    let max = bars
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    let mut img = RgbaImage::from_pixel(WIDTH, height, BACKGROUND);
+
+    let scale = FONT.pt_to_px_scale(FONT_SIZE).unwrap();
+    draw_text_mut(&mut img, WHITE, 8, 10, scale, &*FONT, title);
+    let bar_x = 8 + ICON + 8;
+    let plot_width = (WIDTH - bar_x - COUNT_WIDTH) as f32;
+    for (i, (icon, count)) in bars.iter().enumerate() {
+        let y = TITLE_HEIGHT + i as u32 * ROW;
+        if let Some(icon) = icon {
+            let icon = icon.resize(ICON, ICON, FilterType::Triangle).to_rgba8();
+            // centered if the emoji isn't square
+            let icon_x = 8 + (ICON - icon.width()) / 2;
+            let icon_y = y + (ICON - icon.height()) / 2;
+            overlay(&mut img, &icon, icon_x as i64, icon_y as i64);
+        }
+        let width = (*count as f32 / max as f32 * plot_width).round().max(1.0) as u32;
+        let rect = Rect::at(bar_x as i32, (y + 4) as i32).of_size(width, ICON - 8);
+        draw_filled_rect_mut(&mut img, rect, BLURPLE);
+        draw_text_mut(
+            &mut img,
+            WHITE,
+            (bar_x + width + 6) as i32,
+            (y + 4) as i32,
+            scale,
+            &*FONT,
+            &count.to_string(),
+        );
+    }
+
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Mix `from` and `to`, 0 is all `from`
+fn blend(from: Rgba<u8>, to: Rgba<u8>, ratio: f32) -> Rgba<u8> {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * ratio).round() as u8;
+    Rgba([
+        mix(from[0], to[0]),
+        mix(from[1], to[1]),
+        mix(from[2], to[2]),
+        255,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daily_chart() {
+        let first = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+        let last = NaiveDate::from_ymd_opt(2026, 1, 10).unwrap();
+        let counts = HashMap::from([(last, 3)]);
+        let img = daily_chart(first, last, &counts).to_rgba8();
+        assert_eq!(img.dimensions(), (WIDTH, HEIGHT));
+        // the only bar is the last one and reaches the top of the plot
+        assert_eq!(*img.get_pixel(WIDTH - MARGIN - 50, MARGIN + 1), BLURPLE);
+        assert_eq!(*img.get_pixel(MARGIN + 10, HEIGHT - MARGIN - 1), BACKGROUND);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut counts = [[0; 24]; 7];
+        counts[6][23] = 5;
+        counts[0][0] = 1;
+        let img = heatmap("Activity", &counts).to_rgba8();
+        let cell = |day: u32, hour: u32| {
+            *img.get_pixel(LABEL_WIDTH + hour * CELL + 1, TITLE_HEIGHT + day * CELL + 1)
+        };
+        assert_eq!(cell(6, 23), BLURPLE);
+        assert_eq!(cell(3, 12), EMPTY_CELL);
+        assert_ne!(cell(0, 0), EMPTY_CELL);
+        assert_ne!(cell(0, 0), BLURPLE);
+    }
+
+    #[test]
+    fn test_emoji_chart() {
+        let red = Rgba([255, 0, 0, 255]);
+        let icon = DynamicImage::ImageRgba8(RgbaImage::from_pixel(48, 48, red));
+        let img = emoji_chart("Emojis", &[(Some(icon), 10), (None, 5)]).to_rgba8();
+        assert_eq!(
+            img.dimensions(),
+            (WIDTH, TITLE_HEIGHT + 2 * ROW + MARGIN / 2)
+        );
+        assert_eq!(*img.get_pixel(8 + ICON / 2, TITLE_HEIGHT + ICON / 2), red);
+        assert_eq!(
+            *img.get_pixel(8 + ICON / 2, TITLE_HEIGHT + ROW + ICON / 2),
+            BACKGROUND
+        );
+        // the first bar is the longest, the second one half as long
+        let bar_y = |row: u32| TITLE_HEIGHT + row * ROW + ICON / 2;
+        let end = WIDTH - COUNT_WIDTH - 1;
+        assert_eq!(*img.get_pixel(end, bar_y(0)), BLURPLE);
+        assert_eq!(*img.get_pixel(end, bar_y(1)), BACKGROUND);
+        assert_eq!(*img.get_pixel(WIDTH / 2 - 20, bar_y(1)), BLURPLE);
+    }
+}
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 
-use crate::commands::utils::{parse_date, parse_duration_or_date};
+use crate::commands::utils::{check_guild_channel, parse_date, parse_duration_or_date};
+use crate::guild_settings::{get_guild_settings, invalidate_guild_settings};
 use crate::{Context, done};
 use chrono::Duration;
 use chrono::Utc;
@@ -8,8 +9,9 @@ use ics::properties::{Description, DtEnd, DtStart, Location, Summary};
 use ics::{Event, ICalendar};
 use image::EncodableLayout;
 use poise::serenity_prelude::{
-    CreateAttachment, CreateScheduledEvent, CreateThread, ScheduledEventType,
+    ChannelId, CreateAttachment, CreateScheduledEvent, CreateThread, ScheduledEventType,
 };
+use sqlx::query;
 use std::ops::Add;
 
 const EVENT_URL: &str = "https://discord.com/events/";
@@ -102,10 +104,12 @@ pub(crate) async fn event(
         event.id,
         ctx.author()
     );
-    let announcement_channel = ctx.data().event_channel_per_guild.get(&guild_id);
+    let announcement_channel = get_guild_settings(&ctx.data().database, guild_id)
+        .await?
+        .event_channel;
     match announcement_channel {
         None => {
-            ctx.reply("Event has been created. To also send an announcement and create a thread, configure a channel for this server").await?;
+            ctx.reply("Event has been created. To also send an announcement and create a thread, set a channel with /event_channel").await?;
             Ok(())
         }
         Some(channel) => {
@@ -118,3 +122,31 @@ pub(crate) async fn event(
         }
     }
 }
+
+/// Announce new events in the channel with a thread for each, without a channel nothing is announced
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn event_channel(
+    ctx: Context<'_>,
+    channel: Option<ChannelId>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    if let Some(channel) = channel {
+        check_guild_channel(ctx, channel).await?;
+    }
+    query!(
+        "INSERT INTO guild_settings (guild_id, event_channel_id) VALUES ($1, $2)
+         ON CONFLICT (guild_id) DO UPDATE SET event_channel_id = $2",
+        guild.get() as i64,
+        channel.map(|c| c.get() as i64)
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
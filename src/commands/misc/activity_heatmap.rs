@@ -0,0 +1,54 @@
+use poise::ChoiceParameter;
+use poise::serenity_prelude::User;
+use sqlx::query;
+
+use crate::Context;
+use crate::commands::chart::heatmap;
+use crate::commands::utils::{TimeRange, send_image};
+use crate::constants::TIMEZONE;
+
+/// Show at which times of the week the server or a member is active
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub(crate) async fn activity_heatmap(
+    ctx: Context<'_>,
+    user: Option<User>,
+    range: Option<TimeRange>,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let range = range.unwrap_or_default();
+    let guild = ctx.guild_id().expect("guild_only");
+    let rows = query!(
+        r#"SELECT EXTRACT(ISODOW FROM hour AT TIME ZONE $4)::int AS "weekday!",
+                  EXTRACT(HOUR FROM hour AT TIME ZONE $4)::int AS "hour!",
+                  SUM(count)::bigint AS "count!"
+           FROM activity_hourly
+           WHERE guild_id = $1 AND ($2::bigint IS NULL OR user_id = $2) AND ($3::timestamptz IS NULL OR hour >= $3)
+           GROUP BY 1, 2"#,
+        guild.get() as i64,
+        user.as_ref().map(|u| u.id.get() as i64),
+        range.start(),
+        TIMEZONE.name()
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+
+    let mut counts = [[0; 24]; 7];
+    for row in rows {
+        // ISO weekdays start at 1 for Monday
+        counts[(row.weekday - 1) as usize][row.hour as usize] = row.count;
+    }
+    let name = match &user {
+        Some(user) => user.name.clone(),
+        None => ctx
+            .guild()
+            .map(|g| g.name.clone())
+            .unwrap_or_else(|| "Server".to_string()),
+    };
+    let title = format!("{name} activity, {}", range.name());
+    send_image(
+        ctx,
+        heatmap(&title, &counts),
+        "activity_heatmap.png".to_string(),
+    )
+    .await
+}
@@ -1,35 +1,93 @@
-use anyhow::Context as _;
-use poise::CreateReply;
+use std::collections::HashMap;
+
 use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter, UserId};
+use poise::{ChoiceParameter, CreateReply};
 use sqlx::query;
 
 use crate::Context;
+use crate::commands::chart::daily_chart;
+use crate::commands::utils::{TimeRange, png_attachment, today};
+
+const CHART_NAME: &str = "keyword_usage.png";
 
-/// Show keyword usage statistics
-#[poise::command(slash_command, prefix_command, aliases("kw"))]
-pub(crate) async fn keyword_usage(ctx: Context<'_>, keyword: String) -> anyhow::Result<()> {
+/// Show who triggered auto replies with a keyword the most
+#[poise::command(slash_command, prefix_command, guild_only, aliases("kw"))]
+pub(crate) async fn keyword_usage(
+    ctx: Context<'_>,
+    keyword: String,
+    range: Option<TimeRange>,
+    #[description = "how many users to show"]
+    #[min = 1]
+    #[max = 50]
+    top: Option<i64>,
+) -> anyhow::Result<()> {
     ctx.defer().await?;
-    let stats = query!("SELECT user_id, SUM(count)::int AS count FROM auto_replies WHERE keyword ILIKE '%' || $1 || '%' GROUP BY user_id", keyword)
-        .fetch_all(&ctx.data().database)
-        .await?;
-    let mut data: Vec<_> = Vec::new();
-    let mut total = 0;
-    for stat in stats {
+    let range = range.unwrap_or_default();
+    let since = range.since();
+    let database = &ctx.data().database;
+    let guild = ctx.guild_id().expect("guild_only").get() as i64;
+
+    let total = query!(
+        "SELECT SUM(count)::bigint AS count FROM auto_reply_usage
+         WHERE guild_id = $1 AND keyword ILIKE '%' || $2 || '%' AND ($3::date IS NULL OR day >= $3)",
+        guild,
+        keyword,
+        since
+    )
+    .fetch_one(database)
+    .await?
+    .count
+    .unwrap_or_default();
+    let stats = query!(
+        "SELECT user_id, SUM(count)::bigint AS count FROM auto_reply_usage
+         WHERE guild_id = $1 AND keyword ILIKE '%' || $2 || '%' AND ($3::date IS NULL OR day >= $3)
+         GROUP BY user_id ORDER BY count DESC LIMIT $4",
+        guild,
+        keyword,
+        since,
+        top.unwrap_or(10)
+    )
+    .fetch_all(database)
+    .await?;
+    let mut data = Vec::new();
+    for (rank, stat) in stats.into_iter().enumerate() {
         let name = match UserId::new(stat.user_id as u64).to_user(ctx.http()).await {
             Ok(user) => user.name,
             Err(_) => stat.user_id.to_string(),
         };
-        let count = stat.count.context("count is null")?;
-        data.push(format!("{}: {}", name, count));
-        total += count;
+        data.push(format!(
+            "{}. {}: {}",
+            rank + 1,
+            name,
+            stat.count.unwrap_or_default()
+        ));
     }
 
-    let reply = CreateReply::default().embed(
-        CreateEmbed::default()
-            .title(format!("{keyword} statistics"))
-            .description(data.join("\n"))
-            .footer(CreateEmbedFooter::new(format!("Total: {}", total))),
-    );
-    ctx.send(reply).await?;
+    // skips the undated totals from before daily tracking
+    let per_day: HashMap<_, _> = query!(
+        "SELECT day, SUM(count)::bigint AS count FROM auto_reply_usage
+         WHERE guild_id = $1 AND keyword ILIKE '%' || $2 || '%' AND day >= COALESCE($3::date, '1970-01-02')
+         GROUP BY day",
+        guild,
+        keyword,
+        since
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| (row.day, row.count.unwrap_or_default()))
+    .collect();
+
+    let mut embed = CreateEmbed::default()
+        .title(format!("{keyword} statistics, {}", range.name()))
+        .description(data.join("\n"))
+        .footer(CreateEmbedFooter::new(format!("Total: {total}")));
+    let mut reply = CreateReply::default();
+    if let Some(first) = since.or_else(|| per_day.keys().min().copied()) {
+        let chart = daily_chart(first, today(), &per_day);
+        reply = reply.attachment(png_attachment(chart, CHART_NAME.to_string())?);
+        embed = embed.image(format!("attachment://{CHART_NAME}"));
+    }
+    ctx.send(reply.embed(embed)).await?;
     Ok(())
 }
@@ -0,0 +1,151 @@
+use poise::serenity_prelude::{ChannelId, CreateEmbed, CreateEmbedFooter, UserId};
+use poise::{ChoiceParameter, CreateReply};
+use sqlx::query;
+
+use crate::Context;
+use crate::commands::utils::{TimeRange, format_seconds};
+
+/// What is counted in the daily activity
+#[repr(i16)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum ActivityKind {
+    #[default]
+    #[name = "messages"]
+    Message,
+    #[name = "reactions"]
+    Reaction,
+    #[name = "voice joins"]
+    VoiceJoin,
+}
+
+struct Ranked {
+    user_id: i64,
+    rank: i64,
+    value: String,
+}
+
+/// Show who is the most active on this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub(crate) async fn leaderboard(
+    ctx: Context<'_>,
+    range: Option<TimeRange>,
+    #[description = "count only this channel"] channel: Option<ChannelId>,
+    #[description = "what to count, default messages"] count: Option<ActivityKind>,
+    #[description = "how many users to show"]
+    #[min = 1]
+    #[max = 50]
+    top: Option<usize>,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let range = range.unwrap_or_default();
+    let kind = count.unwrap_or_default();
+    let guild = ctx.guild_id().expect("guild_only");
+
+    let ranking = query!(
+        r#"SELECT user_id, count AS "count!", RANK() OVER (ORDER BY count DESC) AS "rank!" FROM (
+             SELECT user_id, SUM(CASE $4::smallint WHEN 0 THEN messages WHEN 1 THEN reactions ELSE voice_joins END)::bigint AS count
+             FROM activity_daily
+             WHERE guild_id = $1 AND ($2::date IS NULL OR day >= $2) AND ($3::bigint IS NULL OR channel_id = $3)
+             GROUP BY user_id
+           ) totals
+           WHERE count > 0 ORDER BY count DESC"#,
+        guild.get() as i64,
+        range.since(),
+        channel.map(|c| c.get() as i64),
+        kind as i16
+    )
+    .fetch_all(&ctx.data().database)
+    .await?
+    .into_iter()
+    .map(|row| Ranked {
+        user_id: row.user_id,
+        rank: row.rank,
+        value: format!("{} {}", row.count, kind.name()),
+    })
+    .collect();
+
+    let title = format!("Most {}, {}", kind.name(), range.name());
+    send_ranking(ctx, title, channel, ranking, top).await
+}
+
+/// Show who spent the most time in voice channels
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub(crate) async fn voicetime(
+    ctx: Context<'_>,
+    range: Option<TimeRange>,
+    #[description = "count only this voice channel"] channel: Option<ChannelId>,
+    #[description = "how many users to show"]
+    #[min = 1]
+    #[max = 50]
+    top: Option<usize>,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let range = range.unwrap_or_default();
+    let guild = ctx.guild_id().expect("guild_only");
+
+    // open sessions count until now, sessions from before the range only with the part in it
+    let ranking = query!(
+        r#"SELECT user_id, seconds AS "seconds!", RANK() OVER (ORDER BY seconds DESC) AS "rank!" FROM (
+             SELECT user_id, SUM(EXTRACT(EPOCH FROM COALESCE(left_at, now()) - GREATEST(joined_at, COALESCE($2, joined_at))))::bigint AS seconds
+             FROM voice_sessions
+             WHERE guild_id = $1 AND ($2::timestamptz IS NULL OR COALESCE(left_at, now()) > $2)
+               AND ($3::bigint IS NULL OR channel_id = $3)
+             GROUP BY user_id
+           ) totals
+           WHERE seconds > 0 ORDER BY seconds DESC"#,
+        guild.get() as i64,
+        range.start(),
+        channel.map(|c| c.get() as i64)
+    )
+    .fetch_all(&ctx.data().database)
+    .await?
+    .into_iter()
+    .map(|row| Ranked {
+        user_id: row.user_id,
+        rank: row.rank,
+        value: format_seconds(row.seconds),
+    })
+    .collect();
+
+    let title = format!("Most time in voice, {}", range.name());
+    send_ranking(ctx, title, channel, ranking, top).await
+}
+
+async fn send_ranking(
+    ctx: Context<'_>,
+    mut title: String,
+    channel: Option<ChannelId>,
+    ranking: Vec<Ranked>,
+    top: Option<usize>,
+) -> anyhow::Result<()> {
+    let mut lines = Vec::new();
+    for row in ranking.iter().take(top.unwrap_or(10)) {
+        let name = match UserId::new(row.user_id as u64).to_user(ctx.http()).await {
+            Ok(user) => user.name,
+            Err(_) => row.user_id.to_string(),
+        };
+        lines.push(format!("{}. {}: {}", row.rank, name, row.value));
+    }
+    if lines.is_empty() {
+        lines.push("Nobody yet".to_string());
+    }
+
+    let author = ctx.author().id.get() as i64;
+    let footer = match ranking.iter().find(|row| row.user_id == author) {
+        Some(row) => format!("You are #{} with {}", row.rank, row.value),
+        None => "You are not ranked yet".to_string(),
+    };
+    if let Some(channel) = channel {
+        let name = channel
+            .name(ctx)
+            .await
+            .unwrap_or_else(|_| channel.to_string());
+        title.push_str(&format!(" in #{name}"));
+    }
+    let embed = CreateEmbed::default()
+        .title(title)
+        .description(lines.join("\n"))
+        .footer(CreateEmbedFooter::new(footer));
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
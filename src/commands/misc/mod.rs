@@ -1,6 +1,10 @@
-pub(crate) use self::{boop::*, keyword_usage::*, man::*, uwu::*};
+pub(crate) use self::{
+    activity_heatmap::*, boop::*, keyword_usage::*, leaderboard::*, man::*, uwu::*,
+};
 
+pub(crate) mod activity_heatmap;
 pub(crate) mod boop;
 pub(crate) mod keyword_usage;
+pub(crate) mod leaderboard;
 pub(crate) mod man;
 pub(crate) mod uwu;
@@ -5,6 +5,7 @@ pub(crate) use self::{
     utility::*,
 };
 
+mod chart;
 mod events;
 mod images;
 mod mensa;
@@ -14,7 +15,7 @@ mod modules;
 mod owner;
 mod reaction_role;
 mod utility;
-mod utils;
+pub(crate) mod utils;
 
 #[macro_export]
 macro_rules! done {
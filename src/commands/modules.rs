@@ -133,26 +133,58 @@ pub(crate) fn get_active_commands(modules: Vec<Module>) -> Vec<Command<Data, any
             Module::Images => vec![floof(), capy(), cutie_pie(), obama()],
             Module::Owner => vec![
                 activity(),
+                celery_history(),
                 inactive(),
                 latency(),
+                #[cfg(feature = "activity")]
+                prune_inactive(),
                 servers(),
                 sql(),
                 refresh_emojis(),
+                reload_config(),
             ],
             Module::Utility => vec![
+                #[cfg(feature = "activity")]
+                activity_tracking(),
+                ask(),
+                autoreact(),
+                autoreply(),
+                celery(),
                 clear(),
                 emoji(),
                 emoji_usage(),
                 emoji_unused(),
                 features(),
                 embed(),
+                fix_my_links(),
+                linkfix(),
                 reminder(),
                 react(),
                 say(),
                 music(),
+                tldr(),
+                #[cfg(feature = "activity")]
+                track_my_activity(),
+                summarize_from_here(),
+            ],
+            Module::Events => vec![
+                event(),
+                event_channel(),
+                export_events(),
+                reaction_role(),
+                birthday(),
+            ],
+            Module::Misc => vec![
+                activity_heatmap(),
+                boop(),
+                keyword_usage(),
+                leaderboard(),
+                voicetime(),
+                uwu(),
+                uwu_text(),
+                ping(),
+                man(),
             ],
-            Module::Events => vec![event(), export_events(), reaction_role(), birthday()],
-            Module::Misc => vec![boop(), keyword_usage(), uwu(), uwu_text(), ping(), man()],
         });
     }
     commands
@@ -1,5 +1,6 @@
 use crate::commands::load_bot_emojis;
-use crate::{Context, done};
+use crate::config::NEW_SERVERS_ONLY;
+use crate::{Context, UserError, done};
 
 /// Test bot function, should respond with "pong!"
 #[poise::command(slash_command, prefix_command)]
@@ -35,6 +36,8 @@ pub(crate) async fn latency(ctx: Context<'_>) -> anyhow::Result<()> {
                 .await?
         }
     };
+    #[cfg(feature = "activity")]
+    ctx.say(ctx.data().activity.metrics()).await?;
     Ok(())
 }
 
@@ -46,3 +49,26 @@ pub(crate) async fn refresh_emojis(ctx: Context<'_>) -> anyhow::Result<()> {
     load_bot_emojis(ctx.serenity_context(), ids).await?;
     done!(ctx);
 }
+
+/// Reload config.hjson and show what changed
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub(crate) async fn reload_config(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let changes =
+        ctx.data().config.reload().map_err(|e| {
+            UserError::err(format!("Config is invalid, keeping the old one:\n{e:#}"))
+        })?;
+    if changes.is_empty() {
+        ctx.say("Reloaded config, nothing changed").await?;
+    } else {
+        let mut message = format!("Reloaded config:\n```diff\n{}\n```", changes.join("\n"));
+        if changes.iter().any(|c| c.ends_with(NEW_SERVERS_ONLY)) {
+            message.push_str(
+                "Servers WoBot is already on keep their settings, change them with \
+                 /event_channel, /linkfix, /autoreact, /autoreply and /celery",
+            );
+        }
+        ctx.say(message).await?;
+    }
+    Ok(())
+}
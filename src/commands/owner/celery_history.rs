@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+use poise::serenity_prelude::{ChannelId, FormattedTimestamp, Timestamp};
+use sqlx::query;
+
+use crate::Context;
+use crate::commands::utils;
+
+/// See or forget the celery facts posted in a channel
+#[poise::command(
+    slash_command,
+    prefix_command,
+    owners_only,
+    subcommands("list", "purge")
+)]
+pub(crate) async fn celery_history(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Latest facts first
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn list(ctx: Context<'_>, channel: ChannelId) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let facts = query!(
+        "SELECT fact, created_at FROM celery_facts WHERE channel_id = $1 ORDER BY created_at DESC",
+        channel.get() as i64
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    let mut lines = VecDeque::from([format!("**{} facts**", facts.len())]);
+    for row in facts {
+        let date = FormattedTimestamp::from(Timestamp::from(row.created_at));
+        lines.push_back(format!("{date}: {}", row.fact));
+    }
+    utils::paginate_text(ctx, &mut lines).await
+}
+
+/// Forget all facts so they may be repeated
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn purge(ctx: Context<'_>, channel: ChannelId) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let deleted = query!(
+        "DELETE FROM celery_facts WHERE channel_id = $1",
+        channel.get() as i64
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    ctx.say(format!("Forgot {} facts", deleted.rows_affected()))
+        .await?;
+    Ok(())
+}
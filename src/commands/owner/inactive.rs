@@ -1,35 +1,130 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::Context;
 use crate::commands::utils;
+use crate::commands::utils::format_seconds;
 use itertools::Itertools;
 use poise::futures_util::StreamExt;
-use poise::serenity_prelude::{Mentionable, RoleId};
+use poise::serenity_prelude::{Mentionable, RoleId, UserId};
 use sqlx::query;
 use tracing::warn;
 
-/// List inactive users, default 60 days, no bots
+/// List inactive users with their time in voice, default 60 days, no bots
 #[poise::command(slash_command, prefix_command, guild_only, owners_only)]
 pub(crate) async fn inactive(
     ctx: Context<'_>,
     days: Option<u32>,
     include_bots: Option<bool>,
     exclude_role: Option<RoleId>,
+    #[description = "voice joins only count as active with this many minutes in voice"]
+    min_voice_minutes: Option<u32>,
 ) -> anyhow::Result<()> {
     ctx.defer().await?;
 
     let days = days.unwrap_or(60);
-    let include_bots = include_bots.unwrap_or(false);
+    let filter = InactiveFilter {
+        days,
+        include_bots: include_bots.unwrap_or(false),
+        exclude_role,
+        min_voice_minutes,
+    };
+    let inactive = find_inactive(ctx, &filter).await?;
+
+    let mut lines: VecDeque<_> = inactive
+        .into_iter()
+        .map(|i| match i.voice_seconds {
+            0 => i.user.mention().to_string(),
+            seconds => format!("{} ({} voice)", i.user.mention(), format_seconds(seconds)),
+        })
+        .chunks(10)
+        .into_iter()
+        .map(|mut chunk| chunk.join(", "))
+        .collect();
+    lines.push_front(format!("Inactive for {} days", days));
+
+    utils::paginate_text(ctx, &mut lines).await?;
+    Ok(())
+}
+
+/// Who counts as inactive
+pub(crate) struct InactiveFilter {
+    pub(crate) days: u32,
+    pub(crate) include_bots: bool,
+    pub(crate) exclude_role: Option<RoleId>,
+    /// voice joins only count as active with this much time in voice
+    pub(crate) min_voice_minutes: Option<u32>,
+}
+
+pub(crate) struct Inactive {
+    pub(crate) user: UserId,
+    /// in the checked days
+    pub(crate) voice_seconds: i64,
+}
+
+/// Members of the guild without activity in the last days
+pub(crate) async fn find_inactive(
+    ctx: Context<'_>,
+    filter: &InactiveFilter,
+) -> anyhow::Result<Vec<Inactive>> {
     let guild = ctx.guild_id().unwrap();
-    let active = query!(
+    let database = &ctx.data().database;
+    let voice_time: HashMap<u64, i64> = query!(
+        r#"SELECT user_id, SUM(EXTRACT(EPOCH FROM COALESCE(left_at, now()) - GREATEST(joined_at, now() - interval '1 day' * $2)))::bigint AS "seconds!"
+           FROM voice_sessions
+           WHERE guild_id = $1 AND COALESCE(left_at, now()) > now() - interval '1 day' * $2
+           GROUP BY user_id"#,
+        guild.get() as i64,
+        filter.days as i32
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| (row.user_id as u64, row.seconds))
+    .collect();
+    let active = match filter.min_voice_minutes {
+        None => query!(
             "SELECT user_id FROM activity WHERE guild_id = $1 AND now() - last_active <= interval '1 day' * $2",
             guild.get() as i64,
-            days as i32
+            filter.days as i32
         )
-        .fetch_all(&ctx.data().database)
+        .fetch_all(database)
         .await?
         .into_iter()
         .map(|row| row.user_id as u64)
+        .collect::<HashSet<_>>(),
+        Some(minutes) => {
+            let mut active = query!(
+                "SELECT DISTINCT user_id FROM activity_daily
+                 WHERE guild_id = $1 AND day > CURRENT_DATE - $2::int AND messages + reactions > 0",
+                guild.get() as i64,
+                filter.days as i32
+            )
+            .fetch_all(database)
+            .await?
+            .into_iter()
+            .map(|row| row.user_id as u64)
+            .collect::<HashSet<_>>();
+            active.extend(
+                voice_time
+                    .iter()
+                    .filter(|(_, seconds)| **seconds >= minutes as i64 * 60)
+                    .map(|(user, _)| *user),
+            );
+            active
+        }
+    };
+    // nothing is recorded for them, so they never count as inactive
+    #[cfg(feature = "activity")]
+    let active = active
+        .into_iter()
+        .chain(
+            ctx.data()
+                .activity_opt_outs
+                .read()
+                .expect("activity_opt_outs")
+                .iter()
+                .map(|user| user.get()),
+        )
         .collect::<HashSet<_>>();
 
     let mut inactive = Vec::new();
@@ -39,25 +134,22 @@ pub(crate) async fn inactive(
         match member_result {
             Ok(member) => {
                 if !active.contains(&member.user.id.get())
-                    && (!member.user.bot || include_bots)
-                    && exclude_role.is_none_or(|role| !member.roles.contains(&role))
+                    && (!member.user.bot || filter.include_bots)
+                    && filter
+                        .exclude_role
+                        .is_none_or(|role| !member.roles.contains(&role))
                 {
-                    inactive.push(member.user.id);
+                    inactive.push(Inactive {
+                        user: member.user.id,
+                        voice_seconds: voice_time
+                            .get(&member.user.id.get())
+                            .copied()
+                            .unwrap_or_default(),
+                    });
                 }
             }
             Err(error) => warn!("Member checking failed: {}", error),
         }
     }
-
-    let mut lines: VecDeque<_> = inactive
-        .into_iter()
-        .map(|u| u.mention().to_string())
-        .chunks(10)
-        .into_iter()
-        .map(|mut chunk| chunk.join(", "))
-        .collect();
-    lines.push_front(format!("Inactive for {} days", days));
-
-    utils::paginate_text(ctx, &mut lines).await?;
-    Ok(())
+    Ok(inactive)
 }
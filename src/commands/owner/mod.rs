@@ -1,6 +1,11 @@
-pub(crate) use self::{activity::*, bot::*, inactive::*, sql::*};
+#[cfg(feature = "activity")]
+pub(crate) use self::prune::*;
+pub(crate) use self::{activity::*, bot::*, celery_history::*, inactive::*, sql::*};
 
 mod activity;
 mod bot;
+mod celery_history;
 mod inactive;
+#[cfg(feature = "activity")]
+mod prune;
 mod sql;
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use itertools::Itertools;
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed,
+    CreateMessage, FormattedTimestamp, FormattedTimestampStyle, MESSAGE_CODE_LIMIT, Mentionable,
+    RoleId,
+};
+use poise::{ChoiceParameter, CreateReply};
+use sqlx::query;
+use tracing::warn;
+
+use crate::activity::guild_activity;
+use crate::commands::owner::inactive::{InactiveFilter, find_inactive};
+use crate::template::Template;
+use crate::{Context, UserError, done};
+
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+const PREVIEW_MEMBERS: usize = 40;
+const DEFAULT_MESSAGE: &str = "Hi! You haven't been active on **{guild}** for {days} days. \
+Send a message, react or join a voice channel {deadline}, otherwise you will be {action}.";
+/// Placeholders of the warning message
+const MESSAGE_VARIABLES: [&str; 4] = ["guild", "days", "deadline", "action"];
+
+#[repr(i16)]
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum PruneAction {
+    #[name = "assign a role"]
+    Role,
+    #[name = "kick"]
+    Kick,
+}
+
+impl From<i16> for PruneAction {
+    fn from(value: i16) -> Self {
+        match value {
+            0 => PruneAction::Role,
+            _ => PruneAction::Kick,
+        }
+    }
+}
+
+impl PruneAction {
+    /// For "you will be ..."
+    fn passive(self) -> &'static str {
+        match self {
+            PruneAction::Role => "marked as inactive",
+            PruneAction::Kick => "kicked",
+        }
+    }
+}
+
+/// What happened to a warned member once the grace period was over
+#[repr(i16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum PruneOutcome {
+    Active,
+    RoleAssigned,
+    Kicked,
+    Left,
+    Failed,
+}
+
+impl From<i16> for PruneOutcome {
+    fn from(value: i16) -> Self {
+        match value {
+            0 => PruneOutcome::Active,
+            1 => PruneOutcome::RoleAssigned,
+            2 => PruneOutcome::Kicked,
+            3 => PruneOutcome::Left,
+            _ => PruneOutcome::Failed,
+        }
+    }
+}
+
+impl PruneOutcome {
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            PruneOutcome::Active => "active again",
+            PruneOutcome::RoleAssigned => "got the role",
+            PruneOutcome::Kicked => "kicked",
+            PruneOutcome::Left => "left on their own",
+            PruneOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// Warn inactive members and act on those who stay inactive
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    owners_only,
+    subcommands("start", "status", "cancel")
+)]
+pub(crate) async fn prune_inactive(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Preview who would be warned, they get a DM once confirmed
+#[poise::command(slash_command, prefix_command)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn start(
+    ctx: Context<'_>,
+    #[description = "inactive for this many days, default 60"] days: Option<u32>,
+    #[description = "what happens after the grace period"] action: PruneAction,
+    #[description = "the role for inactive members"] role: Option<RoleId>,
+    #[description = "days to become active again, default 7"]
+    #[min = 1]
+    #[max = 60]
+    grace_days: Option<u32>,
+    #[description = "DM with {guild}, {days}, {deadline} and {action}"] message: Option<String>,
+    exclude_role: Option<RoleId>,
+    #[description = "voice joins only count as active with this many minutes in voice"]
+    min_voice_minutes: Option<u32>,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    if guild_activity(ctx.data(), guild).is_none() {
+        return Err(UserError::err(
+            "Activity isn't tracked on this server, everyone would stay inactive",
+        ));
+    }
+    if action == PruneAction::Role && role.is_none() {
+        return Err(UserError::err("Choose the role for inactive members"));
+    }
+    let database = &ctx.data().database;
+    let running = query!(
+        "SELECT id FROM inactive_prunes WHERE guild_id = $1 AND finished_at IS NULL",
+        guild.get() as i64
+    )
+    .fetch_optional(database)
+    .await?;
+    if running.is_some() {
+        return Err(UserError::err(
+            "Members were already warned, check the status or cancel first",
+        ));
+    }
+    let template =
+        Template::parse(message.as_deref().unwrap_or(DEFAULT_MESSAGE)).map_err(UserError::err)?;
+    if let Some(unknown) = template
+        .variables()
+        .find(|v| !MESSAGE_VARIABLES.contains(v))
+    {
+        return Err(UserError::err(format!(
+            "Unknown placeholder {{{unknown}}}, use {}",
+            MESSAGE_VARIABLES.map(|v| format!("{{{v}}}")).join(", ")
+        )));
+    }
+
+    let days = days.unwrap_or(60);
+    let filter = InactiveFilter {
+        days,
+        include_bots: false,
+        exclude_role,
+        min_voice_minutes,
+    };
+    let inactive = find_inactive(ctx, &filter).await?;
+    if inactive.is_empty() {
+        return Err(UserError::err(format!(
+            "Nobody was inactive for {days} days"
+        )));
+    }
+    let deadline = Utc::now() + TimeDelta::days(grace_days.unwrap_or(7) as i64);
+    let guild_name = ctx.guild().map(|g| g.name.clone()).unwrap_or_default();
+    let variables = HashMap::from([
+        ("guild".to_string(), guild_name),
+        ("days".to_string(), days.to_string()),
+        (
+            "deadline".to_string(),
+            FormattedTimestamp::new(deadline.into(), Some(FormattedTimestampStyle::RelativeTime))
+                .to_string(),
+        ),
+        ("action".to_string(), action.passive().to_string()),
+    ]);
+    let dm = template.render(&variables);
+    if dm.chars().count() > MESSAGE_CODE_LIMIT {
+        return Err(UserError::err(format!(
+            "The message is longer than {MESSAGE_CODE_LIMIT} characters"
+        )));
+    }
+
+    let mut members = inactive
+        .iter()
+        .take(PREVIEW_MEMBERS)
+        .map(|i| i.user.mention().to_string())
+        .join(", ");
+    if inactive.len() > PREVIEW_MEMBERS {
+        members.push_str(&format!(" and {} more", inactive.len() - PREVIEW_MEMBERS));
+    }
+    let mut action_text = action.name().to_string();
+    if let Some(role) = role {
+        action_text.push_str(&format!(" {}", role.mention()));
+    }
+    let preview = CreateEmbed::new()
+        .title(format!("Warn {} inactive members?", inactive.len()))
+        // fields are too short for long messages
+        .description(&dm)
+        .field("Members", members, false)
+        .field("Then", action_text, true)
+        .field(
+            "Grace period ends",
+            FormattedTimestamp::new(deadline.into(), None).to_string(),
+            true,
+        );
+    let confirm_id = format!("{}confirm", ctx.id());
+    let cancel_id = format!("{}cancel", ctx.id());
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(&confirm_id)
+            .style(ButtonStyle::Danger)
+            .label("Warn them"),
+        CreateButton::new(&cancel_id)
+            .style(ButtonStyle::Secondary)
+            .label("Cancel"),
+    ])];
+    let reply = ctx
+        .send(CreateReply::default().embed(preview).components(buttons))
+        .await?;
+
+    let prefix = ctx.id().to_string();
+    let press = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(CONFIRM_TIMEOUT)
+        .filter(move |mci| mci.data.custom_id.starts_with(&prefix))
+        .await;
+    let confirmed = match press {
+        Some(mci) => {
+            mci.defer(ctx.http()).await?;
+            mci.data.custom_id == confirm_id
+        }
+        None => false,
+    };
+    if !confirmed {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content("Nobody was warned")
+                    .components(vec![]),
+            )
+            .await?;
+        return Ok(());
+    }
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .content("Warning members…")
+                .components(vec![]),
+        )
+        .await?;
+
+    let prune = query!(
+        "INSERT INTO inactive_prunes (guild_id, channel_id, action, role_id, deadline)
+         VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        guild.get() as i64,
+        ctx.channel_id().get() as i64,
+        action as i16,
+        role.map(|r| r.get() as i64),
+        deadline
+    )
+    .fetch_one(database)
+    .await?
+    .id;
+    let mut failed = 0;
+    for member in &inactive {
+        let sent = match member
+            .user
+            .direct_message(ctx, CreateMessage::new().content(&dm))
+            .await
+        {
+            Ok(_) => true,
+            Err(e) => {
+                warn!(user = ?member.user, error = ?e, "Failed to warn inactive member");
+                failed += 1;
+                false
+            }
+        };
+        query!(
+            "INSERT INTO inactive_prune_members (prune_id, user_id, dm_sent) VALUES ($1, $2, $3)",
+            prune,
+            member.user.get() as i64,
+            sent
+        )
+        .execute(database)
+        .await?;
+    }
+
+    let mut summary = format!(
+        "Warned {} members, they have until {} to be active again.",
+        inactive.len() - failed,
+        FormattedTimestamp::new(deadline.into(), None)
+    );
+    if failed > 0 {
+        summary.push_str(&format!(
+            " {failed} don't accept DMs and are included anyway."
+        ));
+    }
+    reply
+        .edit(ctx, CreateReply::default().content(summary))
+        .await?;
+    Ok(())
+}
+
+/// Show the running grace period
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn status(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let Some(prune) = query!(
+        r#"SELECT p.id, p.action, p.role_id, p.deadline,
+                  COUNT(m.user_id) AS "members!", COUNT(m.user_id) FILTER (WHERE m.dm_sent) AS "warned!",
+                  COUNT(m.user_id) FILTER (WHERE EXISTS (
+                      SELECT 1 FROM activity_hourly h
+                      WHERE h.guild_id = p.guild_id AND h.user_id = m.user_id AND h.hour >= date_trunc('hour', p.warned_at)
+                  )) AS "active!"
+           FROM inactive_prunes p JOIN inactive_prune_members m ON m.prune_id = p.id
+           WHERE p.guild_id = $1 AND p.finished_at IS NULL
+           GROUP BY p.id"#,
+        guild.get() as i64
+    )
+    .fetch_optional(&ctx.data().database)
+    .await?
+    else {
+        return Err(UserError::err("No members were warned"));
+    };
+
+    let mut action = PruneAction::from(prune.action).name().to_string();
+    if let Some(role) = prune.role_id {
+        action.push_str(&format!(" {}", RoleId::new(role as u64).mention()));
+    }
+    ctx.say(format!(
+        "{} of {} warned members are active again, {} got the DM. Then: {action} {}",
+        prune.active,
+        prune.members,
+        prune.warned,
+        FormattedTimestamp::new(
+            prune.deadline.into(),
+            Some(FormattedTimestampStyle::RelativeTime)
+        )
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Stop the grace period, nothing happens to the warned members
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn cancel(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let deleted = query!(
+        "DELETE FROM inactive_prunes WHERE guild_id = $1 AND finished_at IS NULL",
+        guild.get() as i64
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(UserError::err("No members were warned"));
+    }
+    done!(ctx);
+}
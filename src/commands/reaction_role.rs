@@ -1,4 +1,5 @@
 use crate::commands::utils;
+use crate::commands::utils::{EmojiKey, guild_emojis};
 use crate::{Context, Data, done};
 use anyhow::Context as _;
 use poise::serenity_prelude;
@@ -7,7 +8,8 @@ use poise::serenity_prelude::{
     ReactionType, RoleId,
 };
 use sqlx::{query, query_as};
-use std::collections::VecDeque;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
 use std::time::Duration;
 use tracing::{info, warn};
 
@@ -75,10 +77,10 @@ async fn add_reaction_role(
         reaction
     );
 
-    let emoji = utils::get_emoji_text(&reaction, ctx.data());
+    let emoji = EmojiKey::try_from(&reaction)?;
     let guild_id = ctx.guild_id().expect("guild_only");
-    query!("INSERT INTO reaction_roles (message_id, channel_id, guild_id, role_id, emoji) VALUES ($1, $2, $3, $4, $5)",
-        message.id.get() as i64, message.channel_id.get() as i64, guild_id.get() as i64, role_id.get() as i64, emoji,
+    query!("INSERT INTO reaction_roles (message_id, channel_id, guild_id, role_id, emoji_id, unicode) VALUES ($1, $2, $3, $4, $5, $6)",
+        message.id.get() as i64, message.channel_id.get() as i64, guild_id.get() as i64, role_id.get() as i64, emoji.emoji_id(), emoji.unicode(),
     )
         .execute(&ctx.data().database).await
         .context("Adding reaction role failed, is the role/emoji already assigned to this message?")?;
@@ -123,11 +125,12 @@ async fn remove_reaction_role(ctx: Context<'_>, reaction: Reaction) -> anyhow::R
             .link(reaction.channel_id, reaction.guild_id),
         reaction.emoji
     );
-    let emoji = utils::get_emoji_text(&reaction.emoji, ctx.data());
+    let emoji = EmojiKey::try_from(&reaction.emoji)?;
     query!(
-        "DELETE FROM reaction_roles WHERE message_id = $1 AND emoji = $2",
+        "DELETE FROM reaction_roles WHERE message_id = $1 AND emoji_id IS NOT DISTINCT FROM $2 AND unicode IS NOT DISTINCT FROM $3",
         reaction.message_id.get() as i64,
-        emoji
+        emoji.emoji_id(),
+        emoji.unicode()
     )
     .execute(&ctx.data().database)
     .await?;
@@ -158,14 +161,19 @@ pub(crate) async fn list(ctx: Context<'_>) -> anyhow::Result<()> {
         .await?
     };
     let mut roles = VecDeque::from(["**Message | Emoji | Role**".to_string()]);
+    let mut emojis_per_guild = HashMap::new();
     for reaction_role in reaction_roles {
-        let guild = Some(GuildId::new(reaction_role.guild_id as u64));
+        let guild = GuildId::new(reaction_role.guild_id as u64);
+        if let Entry::Vacant(entry) = emojis_per_guild.entry(guild) {
+            entry.insert(guild_emojis(ctx, guild).await?);
+        }
+        let emoji = EmojiKey::from_columns(reaction_role.emoji_id, reaction_role.unicode);
         let channel_id = reaction_role.channel_id as u64;
         let msg_id = reaction_role.message_id as u64;
         roles.push_back(format!(
             "{} {} {}",
-            MessageId::new(msg_id).link(ChannelId::new(channel_id), guild),
-            reaction_role.emoji,
+            MessageId::new(msg_id).link(ChannelId::new(channel_id), Some(guild)),
+            emoji.display(&emojis_per_guild[&guild]),
             RoleId::new(reaction_role.role_id as u64).mention()
         ));
     }
@@ -188,11 +196,14 @@ pub(crate) async fn change_reaction_role(
         return Ok(());
     }
 
-    let emoji = utils::get_emoji_text(&reaction.emoji, data);
+    let Ok(emoji) = EmojiKey::try_from(&reaction.emoji) else {
+        return Ok(());
+    };
     let reaction_role = query!(
-        "SELECT * FROM reaction_roles WHERE message_id = $1 AND emoji = $2",
+        "SELECT * FROM reaction_roles WHERE message_id = $1 AND emoji_id IS NOT DISTINCT FROM $2 AND unicode IS NOT DISTINCT FROM $3",
         reaction.message_id.get() as i64,
-        emoji
+        emoji.emoji_id(),
+        emoji.unicode()
     )
     .fetch_optional(&data.database)
     .await?;
@@ -248,5 +259,6 @@ struct ReactionRole {
     channel_id: i64,
     guild_id: i64,
     role_id: i64,
-    emoji: String,
+    emoji_id: Option<i64>,
+    unicode: Option<String>,
 }
@@ -1,3 +1,5 @@
+use crate::guild_settings::get_guild_settings;
+use crate::link_fix::{expand_short_link, fix_link};
 use crate::{Context, done};
 use itertools::Itertools;
 use poise::serenity_prelude::{Emoji, GuildId, Message, ReactionType};
@@ -8,25 +10,19 @@ use tracing::info;
 
 /// Create embeds and remove tracking parameters from URLs
 #[poise::command(slash_command, prefix_command, track_edits)]
-pub(crate) async fn embed(ctx: Context<'_>, mut url: Url) -> anyhow::Result<()> {
-    if let Some(mut host) = url.host_str() {
-        if let Some(stripped) = host.strip_prefix("www.") {
-            host = stripped;
-        }
-        if let Some(fix) = ctx.data().link_fixes.get(host) {
-            if let Some(tracking) = &fix.tracking {
-                let query = url
-                    .query_pairs()
-                    .filter(|(key, _)| key != tracking)
-                    .map(|(k, v)| format!("{}={}", k, v))
-                    .join("&");
-                url.set_query(Some(&query));
-            }
-            if let Some(host) = &fix.host {
-                url.set_host(Some(host))?;
-            }
-        }
-    }
+pub(crate) async fn embed(ctx: Context<'_>, url: Url) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let mut url = expand_short_link(url).await;
+    let guild_settings = match ctx.guild_id() {
+        Some(guild) => Some(get_guild_settings(&ctx.data().database, guild).await?),
+        None => None,
+    };
+    // outside of guilds, use the defaults from the config
+    let defaults = ctx.data().config.get();
+    let link_fixes = guild_settings
+        .as_ref()
+        .map_or(&defaults.link_fixes, |s| &s.link_fixes);
+    fix_link(link_fixes, &mut url);
     ctx.reply(url).await?;
     Ok(())
 }
@@ -64,7 +60,7 @@ async fn autocomplete_emoji_in_text(ctx: Context<'_>, text: &str) -> Vec<String>
     vec![]
 }
 
-async fn autocomplete_emoji(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+pub(crate) async fn autocomplete_emoji(_ctx: Context<'_>, partial: &str) -> Vec<String> {
     let emojis = EMOJI_CACHE.read().await;
     emojis
         .iter()
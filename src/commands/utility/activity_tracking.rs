@@ -0,0 +1,126 @@
+use poise::serenity_prelude::GuildId;
+use sqlx::{PgPool, query};
+
+use crate::activity::{activity_cache, guild_activity, sync_voice_sessions};
+use crate::{Context, done};
+
+/// Record messages, reactions and voice time for the leaderboards and /inactive
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("enable", "disable", "status")
+)]
+pub(crate) async fn activity_tracking(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Start recording activity on this server
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn enable(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    set_tracking(&ctx.data().database, guild, true).await?;
+    ctx.data()
+        .activity_per_guild
+        .write()
+        .expect("activity_per_guild")
+        .entry(guild)
+        .or_insert_with(activity_cache);
+    // whoever is in voice right now would only be counted after rejoining
+    let current = ctx.guild().map(|g| g.clone());
+    if let Some(current) = current {
+        sync_voice_sessions(ctx.data(), &current).await?;
+    }
+    done!(ctx);
+}
+
+/// Stop recording activity on this server, what was recorded stays
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn disable(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let database = &ctx.data().database;
+    set_tracking(database, guild, false).await?;
+    ctx.data()
+        .activity_per_guild
+        .write()
+        .expect("activity_per_guild")
+        .remove(&guild);
+    query!(
+        "UPDATE voice_sessions SET left_at = now(), last_seen = now() WHERE guild_id = $1 AND left_at IS NULL",
+        guild.get() as i64
+    )
+    .execute(database)
+    .await?;
+    done!(ctx);
+}
+
+/// Show whether activity is recorded on this server
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn status(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let message = match guild_activity(ctx.data(), guild) {
+        Some(cache) => format!(
+            "Activity is recorded on this server, {} members were active in the last day",
+            cache.iter().count()
+        ),
+        None => "Activity isn't recorded on this server".to_string(),
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+async fn set_tracking(database: &PgPool, guild: GuildId, enabled: bool) -> anyhow::Result<()> {
+    query!(
+        "INSERT INTO activity_tracking (guild_id, enabled) VALUES ($1, $2)
+         ON CONFLICT (guild_id) DO UPDATE SET enabled = $2",
+        guild.get() as i64,
+        enabled
+    )
+    .execute(database)
+    .await?;
+    Ok(())
+}
+
+/// Choose whether your messages, reactions and voice time are recorded
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn track_my_activity(ctx: Context<'_>, enabled: bool) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let user = ctx.author().id;
+    let database = &ctx.data().database;
+    if enabled {
+        query!(
+            "DELETE FROM activity_opt_outs WHERE user_id = $1",
+            user.get() as i64
+        )
+        .execute(database)
+        .await?;
+        ctx.data()
+            .activity_opt_outs
+            .write()
+            .expect("activity_opt_outs")
+            .remove(&user);
+    } else {
+        query!(
+            "INSERT INTO activity_opt_outs (user_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            user.get() as i64
+        )
+        .execute(database)
+        .await?;
+        ctx.data()
+            .activity_opt_outs
+            .write()
+            .expect("activity_opt_outs")
+            .insert(user);
+        query!(
+            "UPDATE voice_sessions SET left_at = now(), last_seen = now() WHERE user_id = $1 AND left_at IS NULL",
+            user.get() as i64
+        )
+        .execute(database)
+        .await?;
+    }
+    done!(ctx);
+}
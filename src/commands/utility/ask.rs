@@ -0,0 +1,169 @@
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    AutoArchiveDuration, ChannelId, ChannelType, Context as SerenityContext, CreateAllowedMentions,
+    CreateMessage, CreateThread, MESSAGE_CODE_LIMIT, Message,
+};
+use sqlx::{PgPool, query};
+
+use crate::commands::utils::split_text;
+use crate::llm::{ChatMessage, Role};
+use crate::{Context, Data, UserError};
+
+const SYSTEM_PROMPT: &str = "You are WoBot, a friendly Discord bot answering questions in a thread. \
+Keep your answers short and use Discord markdown.";
+/// Only the latest messages are sent along to stay within the model's context
+const MAX_HISTORY: i64 = 40;
+const THREAD_NAME_LIMIT: usize = 100;
+
+/// Ask the LLM, follow-up messages in the thread continue the conversation
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub(crate) async fn ask(ctx: Context<'_>, question: String) -> anyhow::Result<()> {
+    let channel = ctx.guild_channel().await;
+    if channel.is_some_and(|c| {
+        matches!(
+            c.kind,
+            ChannelType::PublicThread | ChannelType::PrivateThread
+        )
+    }) {
+        return Err(UserError::err("Ask in a normal channel to start a thread"));
+    }
+    if !ctx.data().llm.allow_user(ctx.author().id) {
+        return Err(UserError::err("You asked a lot, try again later"));
+    }
+    ctx.defer().await?;
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(format!("**{}** asked: {question}", ctx.author().name))
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?
+        .into_message()
+        .await?;
+    let name: String = question.chars().take(THREAD_NAME_LIMIT).collect();
+    let thread = ctx
+        .channel_id()
+        .create_thread_from_message(
+            ctx.http(),
+            reply.id,
+            CreateThread::new(name).auto_archive_duration(AutoArchiveDuration::OneDay),
+        )
+        .await?;
+
+    let database = &ctx.data().database;
+    query!(
+        "INSERT INTO llm_threads (thread_id, guild_id, user_id) VALUES ($1, $2, $3)",
+        thread.id.get() as i64,
+        thread.guild_id.get() as i64,
+        ctx.author().id.get() as i64
+    )
+    .execute(database)
+    .await?;
+    ctx.data()
+        .llm_threads
+        .write()
+        .expect("llm_threads")
+        .insert(thread.id);
+
+    answer(ctx.serenity_context(), ctx.data(), thread.id, &question).await
+}
+
+/// Continue the conversation if the message was sent in an `/ask` thread
+pub(crate) async fn answer_in_thread(
+    ctx: &SerenityContext,
+    data: &Data,
+    message: &Message,
+) -> anyhow::Result<()> {
+    let is_thread = data
+        .llm_threads
+        .read()
+        .expect("llm_threads")
+        .contains(&message.channel_id);
+    if !is_thread || message.content.is_empty() {
+        return Ok(());
+    }
+    if !data.llm.allow_user(message.author.id) {
+        message
+            .reply(ctx, "You asked a lot, try again later")
+            .await?;
+        return Ok(());
+    }
+    answer(ctx, data, message.channel_id, &message.content).await
+}
+
+async fn answer(
+    ctx: &SerenityContext,
+    data: &Data,
+    thread: ChannelId,
+    question: &str,
+) -> anyhow::Result<()> {
+    let typing = thread.start_typing(&ctx.http);
+    save_message(&data.database, thread, Role::User, question).await?;
+    let mut messages = vec![ChatMessage::new(Role::System, SYSTEM_PROMPT)];
+    messages.extend(history(&data.database, thread).await?);
+
+    let Ok(answer) = data.llm.chat(&messages).await else {
+        typing.stop();
+        thread
+            .say(&ctx.http, "I can't think right now, try again later")
+            .await?;
+        return Ok(());
+    };
+    save_message(&data.database, thread, Role::Assistant, &answer).await?;
+    typing.stop();
+    for part in split_text(&answer, MESSAGE_CODE_LIMIT) {
+        thread
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(part)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+async fn save_message(
+    database: &PgPool,
+    thread: ChannelId,
+    role: Role,
+    content: &str,
+) -> anyhow::Result<()> {
+    query!(
+        "INSERT INTO llm_messages (thread_id, role, content) VALUES ($1, $2, $3)",
+        thread.get() as i64,
+        role.as_str(),
+        content
+    )
+    .execute(database)
+    .await?;
+    Ok(())
+}
+
+/// The latest messages of the thread, oldest first
+async fn history(database: &PgPool, thread: ChannelId) -> anyhow::Result<Vec<ChatMessage>> {
+    let mut messages: Vec<_> = query!(
+        "SELECT role, content FROM llm_messages WHERE thread_id = $1 ORDER BY id DESC LIMIT $2",
+        thread.get() as i64,
+        MAX_HISTORY
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| ChatMessage::new(Role::from(row.role.as_str()), row.content))
+    .collect();
+    messages.reverse();
+    Ok(messages)
+}
+
+/// Threads with a conversation, loaded at startup
+pub(crate) async fn load_llm_threads(database: &PgPool) -> anyhow::Result<Vec<ChannelId>> {
+    Ok(query!("SELECT thread_id FROM llm_threads")
+        .fetch_all(database)
+        .await?
+        .into_iter()
+        .map(|row| ChannelId::new(row.thread_id as u64))
+        .collect())
+}
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+
+use poise::serenity_prelude::{ChannelId, ReactionType, RoleId};
+use sqlx::query;
+
+use crate::commands::utility::actions::autocomplete_emoji;
+use crate::commands::utility::auto_reply::{change_scope, describe_scope};
+use crate::commands::utils;
+use crate::config::{AutoReaction, RuleScope};
+use crate::guild_settings::{ScopeColumns, get_guild_settings, invalidate_guild_settings};
+use crate::{Context, UserError, done};
+
+/// Manage automatic reactions to keywords
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("add", "scope", "remove", "list")
+)]
+pub(crate) async fn autoreact(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// React with the emoji whenever the word is used, replaces an existing reaction
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn add(
+    ctx: Context<'_>,
+    #[description = "single word"] keyword: String,
+    #[autocomplete = "autocomplete_emoji"] emoji: ReactionType,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let reaction = AutoReaction {
+        keyword: keyword.trim().to_lowercase(),
+        emoji,
+        scope: RuleScope::default(),
+    };
+    let errors = reaction.validate();
+    if !errors.is_empty() {
+        return Err(UserError::err(errors.join("\n")));
+    }
+
+    let guild = ctx.guild_id().expect("guild_only");
+    query!(
+        "INSERT INTO guild_auto_reactions (guild_id, keyword, emoji) VALUES ($1, $2, $3)
+         ON CONFLICT (guild_id, keyword) DO UPDATE SET emoji = $3",
+        guild.get() as i64,
+        reaction.keyword,
+        reaction.emoji.to_string()
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+
+    ctx.say(format!(
+        "Reacting to {} with {}",
+        reaction.keyword, reaction.emoji
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Limit where and how often an auto reaction is added, channels and roles are toggled
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn scope(
+    ctx: Context<'_>,
+    keyword: String,
+    #[description = "seconds per channel, 0 to remove"] channel_cooldown: Option<u32>,
+    #[description = "seconds per user, 0 to remove"] user_cooldown: Option<u32>,
+    #[description = "only react in the allowed channels"] allow_channel: Option<ChannelId>,
+    #[description = "never react in this channel"] deny_channel: Option<ChannelId>,
+    #[description = "never react to members with this role"] exempt_role: Option<RoleId>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let keyword = keyword.trim().to_lowercase();
+    let mut reaction = get_guild_settings(&ctx.data().database, guild)
+        .await?
+        .auto_reactions
+        .iter()
+        .find(|r| r.keyword == keyword)
+        .cloned()
+        .ok_or_else(|| UserError::err(format!("No auto reaction for {keyword}")))?;
+    change_scope(
+        &mut reaction.scope,
+        channel_cooldown,
+        user_cooldown,
+        allow_channel,
+        deny_channel,
+        exempt_role,
+    );
+    let errors = reaction.validate();
+    if !errors.is_empty() {
+        return Err(UserError::err(errors.join("\n")));
+    }
+
+    let scope = ScopeColumns::from(&reaction.scope);
+    query!(
+        "UPDATE guild_auto_reactions SET channel_cooldown = $1, user_cooldown = $2, allowed_channels = $3, denied_channels = $4, exempt_roles = $5
+         WHERE guild_id = $6 AND keyword = $7",
+        scope.channel_cooldown,
+        scope.user_cooldown,
+        &scope.allowed_channels,
+        &scope.denied_channels,
+        &scope.exempt_roles,
+        guild.get() as i64,
+        keyword
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+
+    ctx.say(format!("{keyword}: {}", describe_scope(&reaction.scope)))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn remove(ctx: Context<'_>, keyword: String) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let deleted = query!(
+        "DELETE FROM guild_auto_reactions WHERE guild_id = $1 AND keyword = $2",
+        guild.get() as i64,
+        keyword.trim().to_lowercase()
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(UserError::err(format!("No auto reaction for {keyword}")));
+    }
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn list(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let settings = get_guild_settings(&ctx.data().database, guild).await?;
+    if settings.auto_reactions.is_empty() {
+        ctx.say("No auto reactions in this server").await?;
+        return Ok(());
+    }
+    let mut lines = VecDeque::from(["**Keyword | Emoji | Scope**".to_string()]);
+    for reaction in &settings.auto_reactions {
+        lines.push_back(format!(
+            "{} {} | {}",
+            reaction.keyword,
+            reaction.emoji,
+            describe_scope(&reaction.scope)
+        ));
+    }
+    utils::paginate_text(ctx, &mut lines).await
+}
@@ -0,0 +1,355 @@
+use std::collections::{HashMap, VecDeque};
+
+use poise::CreateReply;
+use poise::serenity_prelude::{ChannelId, Colour, RoleId, User};
+use sqlx::query;
+
+use crate::commands::utils;
+use crate::config::{AutoReply, MatchMode, RuleScope};
+use crate::guild_settings::{ScopeColumns, get_guild_settings, invalidate_guild_settings};
+use crate::handler::{ReplyTrigger, render_auto_reply};
+use crate::{Context, UserError, done};
+
+/// Manage automatic replies to keywords
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("add", "edit", "scope", "remove", "list", "preview")
+)]
+pub(crate) async fn autoreply(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+fn parse_keywords(keywords: &str, match_mode: MatchMode) -> Vec<String> {
+    // regexes can contain commas and are case insensitive anyway
+    if match_mode == MatchMode::Regex {
+        return vec![keywords.to_string()];
+    }
+    keywords
+        .split(',')
+        .map(|k| k.trim().to_lowercase())
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+fn parse_colour(colour: &str) -> anyhow::Result<Colour> {
+    u32::from_str_radix(colour.trim_start_matches('#'), 16)
+        .map(Colour::new)
+        .map_err(|_| UserError::err(format!("{colour} is not a hex colour like #f1c40f")))
+}
+
+fn check_valid(reply: &AutoReply) -> anyhow::Result<()> {
+    let errors = reply.validate();
+    if !errors.is_empty() {
+        return Err(UserError::err(errors.join("\n")));
+    }
+    Ok(())
+}
+
+fn toggle<T: PartialEq>(list: &mut Vec<T>, item: T) {
+    match list.iter().position(|i| *i == item) {
+        Some(index) => {
+            list.remove(index);
+        }
+        None => list.push(item),
+    }
+}
+
+/// Apply the options of a `scope` command, channels and roles are toggled and a cooldown of 0 removes it
+pub(super) fn change_scope(
+    scope: &mut RuleScope,
+    channel_cooldown: Option<u32>,
+    user_cooldown: Option<u32>,
+    allow_channel: Option<ChannelId>,
+    deny_channel: Option<ChannelId>,
+    exempt_role: Option<RoleId>,
+) {
+    if let Some(cooldown) = channel_cooldown {
+        scope.channel_cooldown = (cooldown > 0).then_some(cooldown);
+    }
+    if let Some(cooldown) = user_cooldown {
+        scope.user_cooldown = (cooldown > 0).then_some(cooldown);
+    }
+    if let Some(channel) = allow_channel {
+        toggle(&mut scope.allowed_channels, channel);
+    }
+    if let Some(channel) = deny_channel {
+        toggle(&mut scope.denied_channels, channel);
+    }
+    if let Some(role) = exempt_role {
+        toggle(&mut scope.exempt_roles, role);
+    }
+}
+
+/// Invalid replies are found too so they can be fixed
+async fn find_reply(ctx: Context<'_>, id: i32) -> anyhow::Result<AutoReply> {
+    let guild = ctx.guild_id().expect("guild_only");
+    let settings = get_guild_settings(&ctx.data().database, guild).await?;
+    settings
+        .auto_replies
+        .iter()
+        .chain(settings.skipped_replies.iter().map(|(reply, _)| reply))
+        .find(|r| r.id == id)
+        .cloned()
+        .ok_or_else(|| UserError::err(format!("No auto reply with id {id}")))
+}
+
+/// Add an auto reply, placeholders like {author} or {one of: a|b} are filled in, see the README
+#[poise::command(slash_command, prefix_command)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn add(
+    ctx: Context<'_>,
+    #[description = "comma separated, or a single regex"] keywords: String,
+    #[description = "shown as author"] user: User,
+    title: String,
+    description: String,
+    #[description = "mention the user"] ping: Option<bool>,
+    #[description = "hex colour like #f1c40f"] colour: Option<String>,
+    #[description = "chance to skip the reply, between 0 and 1"] chance: Option<f64>,
+    #[description = "how keywords are matched, substring by default"] match_mode: Option<MatchMode>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let match_mode = match_mode.unwrap_or_default();
+    let reply = AutoReply {
+        id: 0,
+        keywords: parse_keywords(&keywords, match_mode),
+        user: user.id,
+        title,
+        description,
+        ping: ping.unwrap_or_default(),
+        colour: colour
+            .as_deref()
+            .map(parse_colour)
+            .transpose()?
+            .unwrap_or_default(),
+        chance,
+        match_mode,
+        scope: RuleScope::default(),
+    };
+    check_valid(&reply)?;
+
+    let guild = ctx.guild_id().expect("guild_only");
+    let id = query!(
+        "INSERT INTO guild_auto_replies (guild_id, keywords, user_id, title, description, ping, colour, chance, match_mode)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+        guild.get() as i64,
+        &reply.keywords,
+        reply.user.get() as i64,
+        reply.title,
+        reply.description,
+        reply.ping,
+        reply.colour.0 as i32,
+        reply.chance,
+        reply.match_mode as i16
+    )
+    .fetch_one(&ctx.data().database)
+    .await?
+    .id;
+    invalidate_guild_settings(guild);
+
+    ctx.say(format!("Added auto reply {id}")).await?;
+    Ok(())
+}
+
+/// Change an auto reply, unset options stay the same
+#[poise::command(slash_command, prefix_command)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn edit(
+    ctx: Context<'_>,
+    id: i32,
+    #[description = "comma separated, or a single regex"] keywords: Option<String>,
+    #[description = "shown as author"] user: Option<User>,
+    title: Option<String>,
+    description: Option<String>,
+    #[description = "mention the user"] ping: Option<bool>,
+    #[description = "hex colour like #f1c40f"] colour: Option<String>,
+    #[description = "chance to skip, negative to remove"] chance: Option<f64>,
+    #[description = "how keywords are matched"] match_mode: Option<MatchMode>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let mut reply = find_reply(ctx, id).await?;
+    if let Some(match_mode) = match_mode {
+        reply.match_mode = match_mode;
+    }
+    if let Some(keywords) = keywords {
+        reply.keywords = parse_keywords(&keywords, reply.match_mode);
+    }
+    if let Some(user) = user {
+        reply.user = user.id;
+    }
+    if let Some(title) = title {
+        reply.title = title;
+    }
+    if let Some(description) = description {
+        reply.description = description;
+    }
+    if let Some(ping) = ping {
+        reply.ping = ping;
+    }
+    if let Some(colour) = colour {
+        reply.colour = parse_colour(&colour)?;
+    }
+    if let Some(chance) = chance {
+        reply.chance = (chance >= 0.0).then_some(chance);
+    }
+    check_valid(&reply)?;
+
+    let guild = ctx.guild_id().expect("guild_only");
+    query!(
+        "UPDATE guild_auto_replies SET keywords = $1, user_id = $2, title = $3, description = $4, ping = $5, colour = $6, chance = $7, match_mode = $8
+         WHERE id = $9 AND guild_id = $10",
+        &reply.keywords,
+        reply.user.get() as i64,
+        reply.title,
+        reply.description,
+        reply.ping,
+        reply.colour.0 as i32,
+        reply.chance,
+        reply.match_mode as i16,
+        id,
+        guild.get() as i64
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+/// Limit where and how often an auto reply is sent, channels and roles are toggled
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn scope(
+    ctx: Context<'_>,
+    id: i32,
+    #[description = "seconds per channel, 0 to remove"] channel_cooldown: Option<u32>,
+    #[description = "seconds per user, 0 to remove"] user_cooldown: Option<u32>,
+    #[description = "only reply in the allowed channels"] allow_channel: Option<ChannelId>,
+    #[description = "never reply in this channel"] deny_channel: Option<ChannelId>,
+    #[description = "never reply to members with this role"] exempt_role: Option<RoleId>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let mut reply = find_reply(ctx, id).await?;
+    change_scope(
+        &mut reply.scope,
+        channel_cooldown,
+        user_cooldown,
+        allow_channel,
+        deny_channel,
+        exempt_role,
+    );
+    check_valid(&reply)?;
+
+    let guild = ctx.guild_id().expect("guild_only");
+    let scope = ScopeColumns::from(&reply.scope);
+    query!(
+        "UPDATE guild_auto_replies SET channel_cooldown = $1, user_cooldown = $2, allowed_channels = $3, denied_channels = $4, exempt_roles = $5
+         WHERE id = $6 AND guild_id = $7",
+        scope.channel_cooldown,
+        scope.user_cooldown,
+        &scope.allowed_channels,
+        &scope.denied_channels,
+        &scope.exempt_roles,
+        id,
+        guild.get() as i64
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+
+    ctx.say(format!("Auto reply {id}: {}", describe_scope(&reply.scope)))
+        .await?;
+    Ok(())
+}
+
+pub(super) fn describe_scope(scope: &RuleScope) -> String {
+    if *scope == RuleScope::default() {
+        "everywhere, no cooldown".to_string()
+    } else {
+        scope.to_string()
+    }
+}
+
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn remove(ctx: Context<'_>, id: i32) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let deleted = query!(
+        "DELETE FROM guild_auto_replies WHERE id = $1 AND guild_id = $2",
+        id,
+        guild.get() as i64
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(UserError::err(format!("No auto reply with id {id}")));
+    }
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn list(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let settings = get_guild_settings(&ctx.data().database, guild).await?;
+    if settings.auto_replies.is_empty() && settings.skipped_replies.is_empty() {
+        ctx.say("No auto replies in this server").await?;
+        return Ok(());
+    }
+    let mut lines = VecDeque::from(["**ID | Mode | Keywords | Title | Scope**".to_string()]);
+    for reply in &settings.auto_replies {
+        lines.push_back(format!(
+            "`{}` {:?} | {} | {} | {}",
+            reply.id,
+            reply.match_mode,
+            reply.keywords.join(", "),
+            reply.title,
+            describe_scope(&reply.scope)
+        ));
+    }
+    for (reply, errors) in &settings.skipped_replies {
+        lines.push_back(format!(
+            "`{}` is never sent, fix it with `/autoreply edit`: {}",
+            reply.id,
+            errors.join(", ")
+        ));
+    }
+    utils::paginate_text(ctx, &mut lines).await
+}
+
+/// Show an auto reply like it would be sent
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn preview(
+    ctx: Context<'_>,
+    id: i32,
+    #[description = "message to fill in the regex groups"] example: Option<String>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let reply = find_reply(ctx, id).await?;
+    check_valid(&reply)?;
+    let (keyword, captures) = match &example {
+        Some(example) => reply
+            .find_match(example, &example.to_lowercase())
+            .ok_or_else(|| UserError::err("The example doesn't trigger this auto reply"))?,
+        None => (reply.keywords[0].as_str(), HashMap::new()),
+    };
+    let trigger = ReplyTrigger {
+        author: ctx.author(),
+        channel: ctx.channel_id(),
+        guild_id: ctx.guild_id().expect("guild_only"),
+        guild: ctx.guild().map(|g| g.name.clone()).unwrap_or_default(),
+        keyword,
+        captures,
+    };
+    let m = render_auto_reply(
+        ctx.http(),
+        &ctx.data().database,
+        &reply,
+        trigger,
+        CreateReply::default(),
+    )
+    .await?;
+    ctx.send(m).await?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+use poise::serenity_prelude::{ChannelId, Mentionable};
+use sqlx::query;
+
+use crate::commands::utils;
+use crate::commands::utils::check_guild_channel;
+use crate::guild_settings::{get_guild_settings, invalidate_guild_settings};
+use crate::{Context, UserError, done};
+
+/// Post facts from the LLM in a channel now and then
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("enable", "disable", "list")
+)]
+pub(crate) async fn celery(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Post facts in the channel, replaces its current prompt, chance and cooldown
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn enable(
+    ctx: Context<'_>,
+    channel: ChannelId,
+    #[description = "what the facts are about"] prompt: String,
+    #[description = "chance per message, between 0 and 1"] chance: f64,
+    #[description = "messages until the next fact"] cooldown: u32,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    if !(0.0..=1.0).contains(&chance) {
+        return Err(UserError::err("The chance must be between 0 and 1"));
+    }
+    let guild = ctx.guild_id().expect("guild_only");
+    check_guild_channel(ctx, channel).await?;
+    query!(
+        "INSERT INTO guild_celery (channel_id, guild_id, prompt, chance, cooldown) VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (channel_id) DO UPDATE SET prompt = $3, chance = $4, cooldown = $5",
+        channel.get() as i64,
+        guild.get() as i64,
+        prompt,
+        chance,
+        i64::from(cooldown)
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+/// Stop posting facts in the channel, the facts so far stay in /celery_history
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn disable(ctx: Context<'_>, channel: ChannelId) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let deleted = query!(
+        "DELETE FROM guild_celery WHERE channel_id = $1 AND guild_id = $2",
+        channel.get() as i64,
+        guild.get() as i64
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(UserError::err(format!(
+            "No celery facts in {}",
+            channel.mention()
+        )));
+    }
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn list(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let settings = get_guild_settings(&ctx.data().database, guild).await?;
+    if settings.celery.is_empty() {
+        ctx.say("No celery facts in this server").await?;
+        return Ok(());
+    }
+    let mut lines = VecDeque::from(["**Channel | Chance | Cooldown | Prompt**".to_string()]);
+    for (channel, celery) in settings.celery.iter().sorted_by_key(|(c, _)| **c) {
+        lines.push_back(format!(
+            "{} {} | {} messages | {}",
+            channel.mention(),
+            celery.chance,
+            celery.cooldown,
+            celery.prompt
+        ));
+    }
+    utils::paginate_text(ctx, &mut lines).await
+}
@@ -1,5 +1,6 @@
 use anyhow::Context as _;
 use base64::Engine;
+use itertools::Itertools;
 use poise::CreateReply;
 use poise::serenity_prelude::{
     Attachment, ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
@@ -9,13 +10,14 @@ use regex::Regex;
 use std::sync::LazyLock;
 use std::time::Duration;
 use tracing::error;
+use unicode_properties::{EmojiStatus, UnicodeEmoji};
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::commands::utils::remove_components_but_keep_embeds;
-use crate::constants::HTTP_CLIENT;
+use crate::commands::utils::{EmojiKey, remove_components_but_keep_embeds};
+use crate::constants::{EMOJI_URL, HTTP_CLIENT};
 use crate::{Context, UserError, done};
 
 const ADD_EMOJIS_TIMEOUT: Duration = Duration::from_secs(30);
-const EMOJI_URL: &str = "https://cdn.discordapp.com/emojis/";
 const EMOJI_FORMAT: &str = "png";
 static EMOJI_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new("<:([a-zA-Z0-9_]+):([0-9]+)>").expect("EMOJI_REGEX"));
@@ -155,6 +157,32 @@ fn extract_emojis(content: String) -> Vec<NewEmoji> {
     emojis
 }
 
+/// Custom and Unicode emojis, each only once
+pub(crate) fn emojis_in_text(content: &str) -> Vec<EmojiKey> {
+    let custom = EMOJI_REGEX
+        .captures_iter(content)
+        .chain(ANIMATED_EMOJI_REGEX.captures_iter(content))
+        .filter_map(|c| Some(EmojiKey::Custom(c[2].parse().ok()?)));
+    let unicode = content
+        .graphemes(true)
+        .filter(|g| is_unicode_emoji(g))
+        .map(|g| EmojiKey::Unicode(g.to_string()));
+    custom.chain(unicode).unique().collect()
+}
+
+fn is_unicode_emoji(grapheme: &str) -> bool {
+    let Some(first) = grapheme.chars().next() else {
+        return false;
+    };
+    match first.emoji_status() {
+        EmojiStatus::EmojiPresentation
+        | EmojiStatus::EmojiPresentationAndModifierBase
+        | EmojiStatus::EmojiPresentationAndEmojiComponent => true,
+        // text like ❤ or 1 only becomes an emoji with the variation selector
+        _ => first.is_emoji_char() && grapheme.contains('\u{FE0F}'),
+    }
+}
+
 /// Manage emojis
 #[poise::command(
     slash_command,
@@ -254,3 +282,26 @@ pub(crate) async fn upload(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poise::serenity_prelude::EmojiId;
+
+    #[test]
+    fn test_emojis_in_text() {
+        let unicode = |emoji: &str| EmojiKey::Unicode(emoji.to_string());
+        assert_eq!(
+            emojis_in_text("hi <:wave:123> 👋🏽 <a:dance:456> ❤️ ❤ 1 1️⃣ 🇩🇪 :) © 👋🏽 <:hi:123>"),
+            [
+                EmojiKey::Custom(EmojiId::new(123)),
+                EmojiKey::Custom(EmojiId::new(456)),
+                unicode("👋🏽"),
+                unicode("❤️"),
+                unicode("1️⃣"),
+                unicode("🇩🇪")
+            ]
+        );
+        assert!(emojis_in_text("no emojis: 100% <3").is_empty());
+    }
+}
@@ -1,35 +1,196 @@
+#[cfg(feature = "activity")]
+use crate::activity::is_opted_out;
+use crate::commands::chart::emoji_chart;
+use crate::commands::utility::emoji::emojis_in_text;
 use crate::commands::utils;
-use crate::{Context, Data};
+use crate::commands::utils::{EmojiKey, TimeRange, guild_emojis, load_emoji, send_image, today};
+use crate::{Context, Data, UserError};
 use itertools::Itertools;
-use poise::serenity_prelude::{GuildId, Reaction};
-use sqlx::{query, query_as};
+use poise::ChoiceParameter;
+use poise::futures_util::future::join_all;
+use poise::serenity_prelude::{GuildId, Message, Reaction, User, UserId};
+use sqlx::query;
 use std::collections::{HashSet, VecDeque};
 use tracing::warn;
 
+/// Where an emoji was used
+#[repr(i16)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum EmojiSource {
+    #[name = "reactions"]
+    Reactions,
+    #[name = "messages"]
+    Messages,
+    #[default]
+    #[name = "both"]
+    Both,
+}
+
+/// An emoji with its count and what the list shows behind it
+struct UsageRow {
+    emoji: EmojiKey,
+    count: i64,
+    detail: Option<String>,
+}
+
 /// List emoji usage statistics for a guild
 #[poise::command(slash_command, prefix_command, guild_only, aliases("emojis"))]
-pub(crate) async fn emoji_usage(ctx: Context<'_>, guild_id: Option<GuildId>) -> anyhow::Result<()> {
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn emoji_usage(
+    ctx: Context<'_>,
+    guild_id: Option<GuildId>,
+    #[description = "reactions, messages or both, default both"] source: Option<EmojiSource>,
+    range: Option<TimeRange>,
+    #[description = "the favourite emojis of this user"] user: Option<User>,
+    #[description = "used more than in the period before the range"] trending: Option<bool>,
+    #[description = "show the top emojis as a bar chart"] chart: Option<bool>,
+    #[description = "how many emojis the chart shows, default 20"]
+    #[min = 1]
+    #[max = 50]
+    top: Option<usize>,
+) -> anyhow::Result<()> {
     ctx.defer().await?;
     let guild_id = guild_id.unwrap_or(ctx.guild_id().expect("guild_only"));
-    let emoji_stats = query_as!(
-        EmojiUsage,
-        "SELECT * FROM emoji_usage WHERE guild_id = $1 ORDER BY count DESC",
-        guild_id.get() as i64
-    )
-    .fetch_all(&ctx.data().database)
-    .await?;
-    if emoji_stats.is_empty() {
+    let source = source.unwrap_or_default();
+    let range = range.unwrap_or_default();
+    let user_id = user.as_ref().map(|u| u.id.get() as i64);
+
+    let mut title = match &user {
+        Some(user) => format!("Favourite emojis of {}", user.name),
+        None => "Emoji | Usage Count".to_string(),
+    };
+    let rows: Vec<_> = if trending.unwrap_or_default() {
+        let Some(days) = range.days() else {
+            return Err(UserError::err("Choose a time range to compare"));
+        };
+        title.push_str(&format!(", trending {}", range.name()));
+        trending_emojis(ctx, guild_id, source, range, days, user_id)
+            .await?
+            .into_iter()
+            .map(|(emoji, current, previous)| UsageRow {
+                emoji,
+                count: current,
+                detail: Some(match previous {
+                    0 => "new".to_string(),
+                    previous => format!("+{}", current - previous),
+                }),
+            })
+            .collect()
+    } else {
+        title.push_str(&format!(", {}", range.name()));
+        query!(
+            r#"SELECT emoji_id, unicode, reactions AS "reactions!", messages AS "messages!", count AS "count!" FROM (
+                 SELECT emoji_id, unicode, SUM(reactions)::bigint AS reactions, SUM(messages)::bigint AS messages,
+                        SUM(CASE $2::smallint WHEN 0 THEN reactions WHEN 1 THEN messages ELSE reactions + messages END)::bigint AS count
+                 FROM emoji_usage_daily
+                 WHERE guild_id = $1 AND ($3::date IS NULL OR day >= $3) AND ($4::bigint IS NULL OR user_id = $4)
+                 GROUP BY emoji_id, unicode
+               ) usage
+               WHERE count > 0 ORDER BY count DESC"#,
+            guild_id.get() as i64,
+            source as i16,
+            range.since(),
+            user_id
+        )
+        .fetch_all(&ctx.data().database)
+        .await?
+        .into_iter()
+        .map(|stat| UsageRow {
+            emoji: EmojiKey::from_columns(stat.emoji_id, stat.unicode),
+            count: stat.count,
+            detail: (source == EmojiSource::Both).then(|| {
+                format!(
+                    "{} reactions, {} in messages",
+                    stat.reactions, stat.messages
+                )
+            }),
+        })
+        .collect()
+    };
+    if rows.is_empty() {
         ctx.reply("No emoji usage recorded in this guild.").await?;
         return Ok(());
     }
-    let mut lines = VecDeque::from(["**Emoji | Usage Count**".to_string()]);
-    for stat in emoji_stats {
-        lines.push_back(format!("{} {}", stat.emoji, stat.count));
+
+    if chart.unwrap_or_default() {
+        let top = &rows[..rows.len().min(top.unwrap_or(20))];
+        let icons = join_all(top.iter().map(|row| load_emoji(row.emoji.image_url()))).await;
+        let bars = top
+            .iter()
+            .zip(icons)
+            .map(|(row, icon)| {
+                let icon = icon
+                    .inspect_err(|e| warn!(emoji = ?row.emoji, error = ?e, "Failed to load emoji"))
+                    .ok();
+                (icon, row.count)
+            })
+            .collect_vec();
+        return send_image(
+            ctx,
+            emoji_chart(&title, &bars),
+            "emoji_usage.png".to_string(),
+        )
+        .await;
     }
+
+    let emojis = guild_emojis(ctx, guild_id).await?;
+    let mut lines: VecDeque<_> = rows
+        .iter()
+        .map(|row| {
+            let emoji = row.emoji.display(&emojis);
+            match &row.detail {
+                Some(detail) => format!("{emoji} {} ({detail})", row.count),
+                None => format!("{emoji} {}", row.count),
+            }
+        })
+        .collect();
+    lines.push_front(format!("**{title}**"));
     utils::paginate_text(ctx, &mut lines).await?;
     Ok(())
 }
 
+/// Emojis used more often in the range than in the same number of days before,
+/// with the counts of both periods
+async fn trending_emojis(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+    source: EmojiSource,
+    range: TimeRange,
+    days: u64,
+    user_id: Option<i64>,
+) -> anyhow::Result<Vec<(EmojiKey, i64, i64)>> {
+    Ok(query!(
+        r#"SELECT emoji_id, unicode, current AS "current!", previous AS "previous!" FROM (
+             SELECT emoji_id, unicode,
+                    COALESCE(SUM(count) FILTER (WHERE day >= $3), 0)::bigint AS current,
+                    COALESCE(SUM(count) FILTER (WHERE day < $3), 0)::bigint AS previous
+             FROM (
+               SELECT emoji_id, unicode, day, CASE $2::smallint WHEN 0 THEN reactions WHEN 1 THEN messages ELSE reactions + messages END AS count
+               FROM emoji_usage_daily
+               WHERE guild_id = $1 AND day >= $3::date - $4::int AND ($5::bigint IS NULL OR user_id = $5)
+             ) usage
+             GROUP BY emoji_id, unicode
+           ) periods
+           WHERE current > previous ORDER BY current - previous DESC, current DESC"#,
+        guild_id.get() as i64,
+        source as i16,
+        range.since(),
+        days as i32,
+        user_id
+    )
+    .fetch_all(&ctx.data().database)
+    .await?
+    .into_iter()
+    .map(|stat| {
+        (
+            EmojiKey::from_columns(stat.emoji_id, stat.unicode),
+            stat.current,
+            stat.previous,
+        )
+    })
+    .collect())
+}
+
 /// List unused emojis in a guild
 #[poise::command(slash_command, prefix_command, guild_only)]
 pub(crate) async fn emoji_unused(
@@ -40,9 +201,8 @@ pub(crate) async fn emoji_unused(
     let guild_id = guild_id.unwrap_or(ctx.guild_id().expect("guild_only"));
 
     let used_custom_emoji_ids = query!(
-        r#"SELECT substring(emoji FROM '<a?:[A-Za-z0-9_]+:(\d+)>')::bigint AS "emoji_id!"
-           FROM emoji_usage
-           WHERE guild_id = $1 AND count > 0 AND position(':' in emoji) > 0"#,
+        r#"SELECT DISTINCT emoji_id AS "emoji_id!" FROM emoji_usage_daily
+           WHERE guild_id = $1 AND reactions + messages > 0 AND emoji_id IS NOT NULL"#,
         guild_id.get() as i64
     )
     .fetch_all(&ctx.data().database)
@@ -67,6 +227,17 @@ pub(crate) async fn emoji_unused(
     Ok(())
 }
 
+/// Users who opted out of activity tracking are counted without their id
+fn recorded_user(data: &Data, user: Option<UserId>) -> i64 {
+    #[cfg(feature = "activity")]
+    if user.is_some_and(|user| is_opted_out(data, user)) {
+        return 0;
+    }
+    #[cfg(not(feature = "activity"))]
+    let _ = data;
+    user.map_or(0, |user| user.get() as i64)
+}
+
 pub(crate) async fn track_emoji_usage(
     data: &Data,
     reaction: &Reaction,
@@ -76,21 +247,33 @@ pub(crate) async fn track_emoji_usage(
         Some(g) => g,
         None => return Ok(()),
     };
-    let emoji = utils::get_emoji_text(&reaction.emoji, data);
+    let Ok(emoji) = EmojiKey::try_from(&reaction.emoji) else {
+        return Ok(());
+    };
+    let user_id = recorded_user(data, reaction.user_id);
     let result = if !added {
+        // the day a reaction was added isn't known, only today's reactions are taken back
         query!(
-            "UPDATE emoji_usage SET count = count - 1 WHERE guild_id = $1 AND emoji = $2",
+            "UPDATE emoji_usage_daily SET reactions = reactions - 1
+             WHERE guild_id = $1 AND emoji_id IS NOT DISTINCT FROM $2 AND unicode IS NOT DISTINCT FROM $3
+               AND day = $4 AND user_id = $5 AND reactions > 0",
             guild_id.get() as i64,
-            emoji
+            emoji.emoji_id(),
+            emoji.unicode(),
+            today(),
+            user_id
         )
         .execute(&data.database)
         .await
     } else {
         query!(
-            "INSERT INTO emoji_usage (guild_id, emoji, count) VALUES ($1, $2, 1)
-         ON CONFLICT (guild_id, emoji) DO UPDATE SET count = emoji_usage.count + 1",
+            "INSERT INTO emoji_usage_daily (guild_id, user_id, emoji_id, unicode, day, reactions) VALUES ($1, $2, $3, $4, $5, 1)
+             ON CONFLICT (guild_id, emoji_id, unicode, day, user_id) DO UPDATE SET reactions = emoji_usage_daily.reactions + 1",
             guild_id.get() as i64,
-            emoji
+            user_id,
+            emoji.emoji_id(),
+            emoji.unicode(),
+            today()
         )
         .execute(&data.database)
         .await
@@ -101,9 +284,34 @@ pub(crate) async fn track_emoji_usage(
     Ok(())
 }
 
-#[allow(dead_code)]
-struct EmojiUsage {
-    guild_id: i64,
-    emoji: String,
-    count: i64,
+/// Count the emojis written in a message, repeating one in the same message counts once
+pub(crate) async fn track_message_emojis(
+    data: &Data,
+    guild_id: GuildId,
+    message: &Message,
+) -> anyhow::Result<()> {
+    let emojis = emojis_in_text(&message.content);
+    if emojis.is_empty() {
+        return Ok(());
+    }
+    let (emoji_ids, unicodes): (Vec<_>, Vec<_>) = emojis
+        .iter()
+        .map(|emoji| (emoji.emoji_id(), emoji.unicode()))
+        .unzip();
+    let result = query!(
+        "INSERT INTO emoji_usage_daily (guild_id, user_id, emoji_id, unicode, day, messages)
+         SELECT $1, $2, emoji_id, unicode, $5, 1 FROM UNNEST($3::bigint[], $4::text[]) AS e(emoji_id, unicode)
+         ON CONFLICT (guild_id, emoji_id, unicode, day, user_id) DO UPDATE SET messages = emoji_usage_daily.messages + 1",
+        guild_id.get() as i64,
+        recorded_user(data, Some(message.author.id)),
+        &emoji_ids as &[Option<i64>],
+        &unicodes as &[Option<&str>],
+        today()
+    )
+    .execute(&data.database)
+    .await;
+    if let Err(e) = result {
+        warn!("Failed to track emoji usage: {}", e);
+    }
+    Ok(())
 }
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+use poise::serenity_prelude::{ChannelId, GuildId};
+use sqlx::{PgPool, query};
+
+use crate::commands::utils;
+use crate::commands::utils::check_guild_channel;
+use crate::config::{ALL_DOMAINS, LinkFix, PathRewrite};
+use crate::guild_settings::{get_guild_settings, invalidate_guild_settings};
+use crate::{Context, UserError, done};
+
+/// Fix links in every message, not just with /embed
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("auto", "channel", "rule", "path", "remove", "list")
+)]
+pub(crate) async fn linkfix(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Turn automatic link fixes on or off for this server
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn auto(
+    ctx: Context<'_>,
+    enabled: bool,
+    #[description = "repost the links with the author's name and avatar"] webhook: Option<bool>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    query!(
+        "INSERT INTO guild_settings (guild_id, auto_link_fix, link_fix_webhook) VALUES ($1, $2, COALESCE($3, false))
+         ON CONFLICT (guild_id) DO UPDATE SET auto_link_fix = $2, link_fix_webhook = COALESCE($3, guild_settings.link_fix_webhook)",
+        guild.get() as i64,
+        enabled,
+        webhook
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+/// Allow or stop automatic link fixes in a channel
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn channel(
+    ctx: Context<'_>,
+    channel: ChannelId,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    check_guild_channel(ctx, channel).await?;
+    let database = &ctx.data().database;
+    if enabled {
+        query!(
+            "DELETE FROM guild_link_fix_opt_outs WHERE channel_id = $1 AND guild_id = $2",
+            channel.get() as i64,
+            guild.get() as i64
+        )
+        .execute(database)
+        .await?;
+    } else {
+        query!(
+            "INSERT INTO guild_link_fix_opt_outs (channel_id, guild_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            channel.get() as i64,
+            guild.get() as i64
+        )
+        .execute(database)
+        .await?;
+    }
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+/// Links of `www.` subdomains use the fix of the domain
+fn parse_domain(domain: &str) -> String {
+    let domain = domain.trim().to_lowercase();
+    domain.strip_prefix("www.").unwrap_or(&domain).to_string()
+}
+
+async fn find_fix(ctx: Context<'_>, domain: &str) -> anyhow::Result<Option<LinkFix>> {
+    let guild = ctx.guild_id().expect("guild_only");
+    Ok(get_guild_settings(&ctx.data().database, guild)
+        .await?
+        .link_fixes
+        .get(domain)
+        .cloned())
+}
+
+async fn save_fix(
+    database: &PgPool,
+    guild: GuildId,
+    domain: &str,
+    fix: &LinkFix,
+) -> anyhow::Result<()> {
+    let errors = fix.validate(domain);
+    if !errors.is_empty() {
+        return Err(UserError::err(errors.join("\n")));
+    }
+    let (patterns, replacements): (Vec<_>, Vec<_>) = fix
+        .paths
+        .iter()
+        .map(|p| (p.pattern.clone(), p.replacement.clone()))
+        .unzip();
+    query!(
+        "INSERT INTO guild_link_fixes (guild_id, domain, host, tracking, path_patterns, path_replacements)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (guild_id, domain) DO UPDATE SET host = $3, tracking = $4, path_patterns = $5, path_replacements = $6",
+        guild.get() as i64,
+        domain,
+        fix.host,
+        &fix.tracking,
+        &patterns,
+        &replacements
+    )
+    .execute(database)
+    .await?;
+    invalidate_guild_settings(guild);
+    Ok(())
+}
+
+/// Change how links of a domain are fixed, unset options stay the same
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn rule(
+    ctx: Context<'_>,
+    #[description = "like reddit.com, * for all links"] domain: String,
+    #[description = "host the links are sent to, empty to keep theirs"] host: Option<String>,
+    #[description = "comma separated query parameters to remove, * matches anything"]
+    tracking: Option<String>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let domain = parse_domain(&domain);
+    let mut fix = find_fix(ctx, &domain).await?.unwrap_or_default();
+    if let Some(host) = host {
+        let host = host.trim();
+        fix.host = (!host.is_empty()).then(|| host.to_string());
+    }
+    if let Some(tracking) = tracking {
+        fix.tracking = tracking
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    let guild = ctx.guild_id().expect("guild_only");
+    save_fix(&ctx.data().database, guild, &domain, &fix).await?;
+    done!(ctx);
+}
+
+/// Rewrite the path of a domain's links, replaces the rewrite with the same pattern
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn path(
+    ctx: Context<'_>,
+    domain: String,
+    #[description = "case-insensitive regex"] pattern: String,
+    #[description = "groups like $1 work, empty removes it"] replacement: Option<String>,
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let domain = parse_domain(&domain);
+    let mut fix = find_fix(ctx, &domain).await?.unwrap_or_default();
+    let existing = fix.paths.iter().position(|p| p.pattern == pattern);
+    match (existing, replacement) {
+        (Some(index), Some(replacement)) => fix.paths[index].replacement = replacement,
+        (None, Some(replacement)) => fix.paths.push(PathRewrite {
+            pattern,
+            replacement,
+        }),
+        (Some(index), None) => {
+            fix.paths.remove(index);
+        }
+        (None, None) => {
+            return Err(UserError::err(format!(
+                "{domain} has no rewrite for {pattern}"
+            )));
+        }
+    }
+    let guild = ctx.guild_id().expect("guild_only");
+    save_fix(&ctx.data().database, guild, &domain, &fix).await?;
+    done!(ctx);
+}
+
+/// Stop fixing links of a domain
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn remove(ctx: Context<'_>, domain: String) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let domain = parse_domain(&domain);
+    let deleted = query!(
+        "DELETE FROM guild_link_fixes WHERE guild_id = $1 AND domain = $2",
+        guild.get() as i64,
+        domain
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(UserError::err(format!("No link fix for {domain}")));
+    }
+    invalidate_guild_settings(guild);
+    done!(ctx);
+}
+
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn list(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild = ctx.guild_id().expect("guild_only");
+    let settings = get_guild_settings(&ctx.data().database, guild).await?;
+    if settings.link_fixes.is_empty() {
+        ctx.say("No link fixes in this server").await?;
+        return Ok(());
+    }
+    let mut lines = VecDeque::from(["**Domain | Host | Tracking | Paths**".to_string()]);
+    // the fix for all domains applies first
+    let fixes = settings
+        .link_fixes
+        .iter()
+        .sorted_by_key(|(domain, _)| (domain.as_str() != ALL_DOMAINS, domain.as_str()));
+    for (domain, fix) in fixes {
+        let paths = fix
+            .paths
+            .iter()
+            .map(|p| format!("`{}` → `{}`", p.pattern, p.replacement))
+            .join(", ");
+        lines.push_back(format!(
+            "{domain} | {} | {} | {paths}",
+            fix.host.as_deref().unwrap_or("-"),
+            fix.tracking.join(", ")
+        ));
+    }
+    utils::paginate_text(ctx, &mut lines).await
+}
+
+/// Choose whether the bot fixes the links you send
+#[poise::command(slash_command, prefix_command)]
+pub(crate) async fn fix_my_links(ctx: Context<'_>, enabled: bool) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+    let user = ctx.author().id.get() as i64;
+    let database = &ctx.data().database;
+    if enabled {
+        query!(
+            "DELETE FROM link_fix_user_opt_outs WHERE user_id = $1",
+            user
+        )
+        .execute(database)
+        .await?;
+    } else {
+        query!(
+            "INSERT INTO link_fix_user_opt_outs (user_id) VALUES ($1) ON CONFLICT DO NOTHING",
+            user
+        )
+        .execute(database)
+        .await?;
+    }
+    done!(ctx);
+}
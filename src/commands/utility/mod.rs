@@ -1,13 +1,24 @@
+#[cfg(feature = "activity")]
+pub(crate) use self::activity_tracking::*;
 pub(crate) use self::{
-    actions::*, birthday::*, clear::*, emoji::*, emoji_usage::*, features::*, music::*, reminder::*,
+    actions::*, ask::*, auto_react::*, auto_reply::*, birthday::*, celery::*, clear::*, emoji::*,
+    emoji_usage::*, features::*, link_fix::*, music::*, reminder::*, tldr::*,
 };
 
 mod actions;
+#[cfg(feature = "activity")]
+mod activity_tracking;
+mod ask;
+mod auto_react;
+mod auto_reply;
 mod birthday;
+mod celery;
 mod clear;
 mod emoji;
 mod emoji_usage;
 mod feature_state;
 mod features;
+mod link_fix;
 mod music;
 mod reminder;
+mod tldr;
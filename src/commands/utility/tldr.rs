@@ -0,0 +1,205 @@
+use std::sync::LazyLock;
+
+use itertools::Itertools;
+use poise::CreateReply;
+use poise::serenity_prelude::{CreateEmbed, GetMessages, Message, MessageId};
+use regex::{Captures, Regex};
+
+use crate::commands::utils::split_text;
+use crate::config::EMBED_DESCRIPTION_LIMIT;
+use crate::{Context, UserError};
+
+const DEFAULT_MESSAGES: u16 = 100;
+const MAX_MESSAGES: usize = 500;
+/// Characters of the transcript per request, small enough for most model contexts
+const CHUNK_SIZE: usize = 12_000;
+const PROMPT: &str = "Summarize this Discord conversation in a few short bullet points. \
+After each point cite the most relevant message numbers in square brackets, like [3].";
+const MERGE_PROMPT: &str = "Combine these summaries of consecutive parts of one Discord conversation \
+into a few short bullet points. Keep the message numbers in square brackets.";
+
+static REFERENCE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[(\d+)]").unwrap());
+
+/// Summarize the latest messages or everything since a message
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub(crate) async fn tldr(
+    ctx: Context<'_>,
+    #[description = "how many of the latest messages, default 100"]
+    #[min = 1]
+    #[max = 500]
+    count: Option<u16>,
+    #[description = "link to the first message to summarize"] since: Option<Message>,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let messages = match since {
+        Some(first) => messages_since(ctx, first).await?,
+        None => latest_messages(ctx, count.unwrap_or(DEFAULT_MESSAGES) as usize).await?,
+    };
+    summarize(ctx, messages).await
+}
+
+#[poise::command(context_menu_command = "Summarize from here", guild_only)]
+pub(crate) async fn summarize_from_here(ctx: Context<'_>, first: Message) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let messages = messages_since(ctx, first).await?;
+    summarize(ctx, messages).await
+}
+
+async fn latest_messages(ctx: Context<'_>, count: usize) -> anyhow::Result<Vec<Message>> {
+    let mut messages: Vec<Message> = Vec::new();
+    while messages.len() < count {
+        let mut request = GetMessages::new().limit((count - messages.len()).min(100) as u8);
+        if let Some(oldest) = messages.last() {
+            request = request.before(oldest.id);
+        }
+        let batch = ctx.channel_id().messages(ctx.http(), request).await?;
+        if batch.is_empty() {
+            break;
+        }
+        // newest first
+        messages.extend(batch);
+    }
+    messages.reverse();
+    Ok(messages)
+}
+
+/// `first` and the messages after it, at most [MAX_MESSAGES]
+async fn messages_since(ctx: Context<'_>, first: Message) -> anyhow::Result<Vec<Message>> {
+    if first.channel_id != ctx.channel_id() {
+        return Err(UserError::err("Pick a message from this channel"));
+    }
+    let channel = first.channel_id;
+    let mut after: MessageId = first.id;
+    let mut messages = vec![first];
+    while messages.len() < MAX_MESSAGES {
+        let request = GetMessages::new().after(after).limit(100);
+        let batch = channel.messages(ctx.http(), request).await?;
+        let Some(newest) = batch.iter().map(|m| m.id).max() else {
+            break;
+        };
+        after = newest;
+        messages.extend(batch);
+    }
+    messages.sort_by_key(|m| m.id);
+    messages.truncate(MAX_MESSAGES);
+    Ok(messages)
+}
+
+async fn summarize(ctx: Context<'_>, messages: Vec<Message>) -> anyhow::Result<()> {
+    let messages = messages
+        .into_iter()
+        .filter(|m| !m.content.is_empty())
+        .collect_vec();
+    if messages.is_empty() {
+        return Err(UserError::err("There is nothing to summarize"));
+    }
+    if !ctx.data().llm.allow_user(ctx.author().id) {
+        return Err(UserError::err("You asked a lot, try again later"));
+    }
+
+    let lines = messages
+        .iter()
+        .enumerate()
+        .map(|(i, m)| format!("[{}] {}: {}", i + 1, m.author.name, m.content))
+        .collect_vec();
+    let mut summaries = Vec::new();
+    for chunk in chunk_lines(&lines, CHUNK_SIZE) {
+        summaries.push(generate(ctx, PROMPT, &chunk).await?);
+    }
+    let summary = merge_summaries(ctx, summaries).await?;
+
+    let links = messages.iter().map(|m| m.link()).collect_vec();
+    let summary = link_references(&summary, &links);
+    for (i, part) in split_text(&summary, EMBED_DESCRIPTION_LIMIT)
+        .into_iter()
+        .enumerate()
+    {
+        let mut embed = CreateEmbed::new().description(part);
+        if i == 0 {
+            embed = embed.title(format!("TL;DR of {} messages", messages.len()));
+        }
+        ctx.send(CreateReply::default().embed(embed)).await?;
+    }
+    Ok(())
+}
+
+/// Merge the summaries a chunk at a time until one is left, so the merge prompts stay small too
+async fn merge_summaries(ctx: Context<'_>, mut summaries: Vec<String>) -> anyhow::Result<String> {
+    while summaries.len() > 1 {
+        let mut chunks = chunk_lines(&summaries, CHUNK_SIZE);
+        // summaries too long to share a chunk are merged in pairs so there are fewer each round
+        if chunks.len() == summaries.len() {
+            chunks = summaries.chunks(2).map(|pair| pair.join("\n")).collect();
+        }
+        let mut merged = Vec::new();
+        for chunk in chunks {
+            merged.push(generate(ctx, MERGE_PROMPT, &chunk).await?);
+        }
+        summaries = merged;
+    }
+    Ok(summaries.remove(0))
+}
+
+async fn generate(ctx: Context<'_>, prompt: &str, text: &str) -> anyhow::Result<String> {
+    ctx.data()
+        .llm
+        .generate(&format!("{prompt}\n\n{text}"))
+        .await
+        .map_err(|_| UserError::err("I can't think right now, try again later"))
+}
+
+/// Join lines into chunks of at most `size` characters, a longer line gets its own chunk
+fn chunk_lines(lines: &[String], size: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for line in lines {
+        if !chunk.is_empty() && chunk.len() + line.len() + 1 > size {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        if !chunk.is_empty() {
+            chunk.push('\n');
+        }
+        chunk.push_str(line);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Turn `[3]` into a jump link to the third message, unknown numbers stay as they are
+fn link_references(summary: &str, links: &[String]) -> String {
+    REFERENCE_REGEX
+        .replace_all(summary, |captures: &Captures| {
+            let number: usize = captures[1].parse().unwrap_or_default();
+            match number.checked_sub(1).and_then(|i| links.get(i)) {
+                Some(link) => format!("[[{number}]]({link})"),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_lines() {
+        let lines = ["aaaa", "bbbb", "cc", "dddddddddd"].map(String::from);
+        assert_eq!(
+            chunk_lines(&lines, 10),
+            vec!["aaaa\nbbbb", "cc", "dddddddddd"]
+        );
+        assert!(chunk_lines(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn test_link_references() {
+        let links = vec!["https://discord.com/channels/1/2/3".to_string()];
+        assert_eq!(
+            link_references("- cake [1], [2]", &links),
+            "- cake [[1]](https://discord.com/channels/1/2/3), [2]"
+        );
+    }
+}
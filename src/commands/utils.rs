@@ -1,17 +1,19 @@
-use crate::constants::{HTTP_CLIENT, ONE_DAY};
-use crate::{Context, Data, UserError};
+use crate::constants::{EMOJI_URL, HTTP_CLIENT, ONE_DAY, TIMEZONE, TWEMOJI_URL};
+use crate::{Context, UserError};
 use anyhow::Context as _;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Days, NaiveDate, TimeZone, Utc};
 use image::DynamicImage;
 use image::codecs::png::PngEncoder;
+use itertools::Itertools;
 use mini_moka::sync::Cache;
 use poise::serenity_prelude::{
-    Colour, CreateAttachment, CreateEmbed, MESSAGE_CODE_LIMIT, ReactionType, User,
+    ChannelId, Colour, CreateAttachment, CreateEmbed, Emoji, EmojiId, GuildId, MESSAGE_CODE_LIMIT,
+    Mentionable, ReactionType, User,
 };
 use poise::{CreateReply, ReplyHandle};
 use rand::prelude::IndexedRandom;
 use rand::rng;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::LazyLock;
 use tokio::process::Command;
 
@@ -49,17 +51,45 @@ static AVATAR_CACHE: LazyLock<Cache<String, DynamicImage>> = LazyLock::new(|| {
         .build()
 });
 
+static EMOJI_CACHE: LazyLock<Cache<String, DynamicImage>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(10 * 1024 * 1024) // 10 MB
+        .time_to_idle(10 * ONE_DAY)
+        .weigher(|_, v: &DynamicImage| v.as_bytes().len() as u32)
+        .build()
+});
+
 pub(crate) async fn load_avatar(avatar_url: String) -> anyhow::Result<DynamicImage> {
-    if let Some(avatar) = AVATAR_CACHE.get(&avatar_url) {
-        return Ok(avatar);
+    load_cached_image(&AVATAR_CACHE, avatar_url)
+        .await
+        .context("Downloading avatar failed")
+}
+
+pub(crate) async fn load_emoji(emoji_url: String) -> anyhow::Result<DynamicImage> {
+    load_cached_image(&EMOJI_CACHE, emoji_url)
+        .await
+        .context("Downloading emoji failed")
+}
+
+async fn load_cached_image(
+    cache: &Cache<String, DynamicImage>,
+    url: String,
+) -> anyhow::Result<DynamicImage> {
+    if let Some(img) = cache.get(&url) {
+        return Ok(img);
     }
 
-    let result = HTTP_CLIENT.get(&avatar_url).send().await;
-    let bytes = result.context("Downloading avatar failed")?.bytes().await?;
-    let avatar = image::load_from_memory(&bytes)?;
-    AVATAR_CACHE.insert(avatar_url, avatar.clone());
+    let bytes = HTTP_CLIENT
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let img = image::load_from_memory(&bytes)?;
+    cache.insert(url, img.clone());
 
-    Ok(avatar)
+    Ok(img)
 }
 
 pub(crate) async fn get_avatar_url(ctx: &Context<'_>, user: &User) -> anyhow::Result<String> {
@@ -103,34 +133,207 @@ pub(crate) async fn remove_components_but_keep_embeds(
     Ok(())
 }
 
+pub fn png_attachment(img: DynamicImage, filename: String) -> anyhow::Result<CreateAttachment> {
+    let mut output_bytes: Vec<u8> = Vec::new();
+    img.write_with_encoder(PngEncoder::new(&mut output_bytes))?;
+    Ok(CreateAttachment::bytes(output_bytes, filename))
+}
+
 pub async fn send_image(
     ctx: Context<'_>,
     img: DynamicImage,
     filename: String,
 ) -> anyhow::Result<()> {
-    let mut output_bytes: Vec<u8> = Vec::new();
-    img.write_with_encoder(PngEncoder::new(&mut output_bytes))?;
-
-    ctx.send(CreateReply::default().attachment(CreateAttachment::bytes(output_bytes, filename)))
+    ctx.send(CreateReply::default().attachment(png_attachment(img, filename)?))
         .await?;
     Ok(())
 }
 
-pub fn get_emoji_text(reaction: &ReactionType, _data: &Data) -> String {
-    match reaction {
-        ReactionType::Custom { id, name, animated } => {
-            format!(
-                "<{}:{}:{}>",
-                if *animated { "a" } else { "" },
-                name.as_deref().unwrap_or("name"),
-                id.get()
-            )
+/// Today in the bot's timezone
+pub(crate) fn today() -> NaiveDate {
+    Utc::now().with_timezone(&TIMEZONE).date_naive()
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, poise::ChoiceParameter)]
+pub(crate) enum TimeRange {
+    #[name = "last 7 days"]
+    Week,
+    #[name = "last 30 days"]
+    Month,
+    #[name = "last 365 days"]
+    Year,
+    #[default]
+    #[name = "all time"]
+    All,
+}
+
+impl TimeRange {
+    /// Length of the range, `None` for all time
+    pub(crate) fn days(self) -> Option<u64> {
+        match self {
+            TimeRange::Week => Some(7),
+            TimeRange::Month => Some(30),
+            TimeRange::Year => Some(365),
+            TimeRange::All => None,
         }
-        ReactionType::Unicode(unicode) => unicode.clone(),
-        _ => unimplemented!(),
+    }
+
+    /// First day in the range, `None` for all time
+    pub(crate) fn since(self) -> Option<NaiveDate> {
+        today().checked_sub_days(Days::new(self.days()? - 1))
+    }
+
+    /// Midnight of the first day in the range
+    pub(crate) fn start(self) -> Option<DateTime<Utc>> {
+        let midnight = self.since()?.and_hms_opt(0, 0, 0)?;
+        let start = TIMEZONE.from_local_datetime(&midnight).earliest()?;
+        Some(start.with_timezone(&Utc))
     }
 }
 
+/// Like `2d 3h 5m`, seconds only for less than a minute
+pub(crate) fn format_seconds(seconds: i64) -> String {
+    if seconds < 60 {
+        return format!("{}s", seconds.max(0));
+    }
+    let minutes = seconds / 60;
+    [
+        (minutes / 1440, "d"),
+        (minutes / 60 % 24, "h"),
+        (minutes % 60, "m"),
+    ]
+    .into_iter()
+    .filter(|(value, _)| *value > 0)
+    .map(|(value, unit)| format!("{value}{unit}"))
+    .join(" ")
+}
+
+/// Custom emojis by id so renames don't matter, Unicode emojis as they are
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum EmojiKey {
+    Custom(EmojiId),
+    Unicode(String),
+}
+
+impl TryFrom<&ReactionType> for EmojiKey {
+    type Error = anyhow::Error;
+
+    /// Fails for reaction types added to Discord later
+    fn try_from(reaction: &ReactionType) -> anyhow::Result<Self> {
+        match reaction {
+            ReactionType::Custom { id, .. } => Ok(EmojiKey::Custom(*id)),
+            ReactionType::Unicode(unicode) => Ok(EmojiKey::Unicode(unicode.clone())),
+            _ => Err(UserError::err("Unsupported emoji")),
+        }
+    }
+}
+
+impl EmojiKey {
+    /// From the `emoji_id` and `unicode` columns, exactly one of them is set
+    pub(crate) fn from_columns(emoji_id: Option<i64>, unicode: Option<String>) -> Self {
+        match (emoji_id, unicode) {
+            (Some(id), _) => EmojiKey::Custom(EmojiId::new(id as u64)),
+            (None, unicode) => EmojiKey::Unicode(unicode.unwrap_or_default()),
+        }
+    }
+
+    pub(crate) fn emoji_id(&self) -> Option<i64> {
+        match self {
+            EmojiKey::Custom(id) => Some(id.get() as i64),
+            EmojiKey::Unicode(_) => None,
+        }
+    }
+
+    pub(crate) fn unicode(&self) -> Option<&str> {
+        match self {
+            EmojiKey::Custom(_) => None,
+            EmojiKey::Unicode(unicode) => Some(unicode),
+        }
+    }
+
+    /// PNG from Discord's CDN or Twemoji, animated emojis only with their first frame
+    pub(crate) fn image_url(&self) -> String {
+        match self {
+            EmojiKey::Custom(id) => format!("{EMOJI_URL}{id}.png?size=64"),
+            EmojiKey::Unicode(unicode) => {
+                // Twemoji drops the variation selector unless the emoji is joined from several
+                let joined = unicode.contains('\u{200D}');
+                let codepoints = unicode
+                    .chars()
+                    .filter(|&c| joined || c != '\u{FE0F}')
+                    .map(|c| format!("{:x}", c as u32))
+                    .join("-");
+                format!("{TWEMOJI_URL}{codepoints}.png")
+            }
+        }
+    }
+
+    /// With the current name from the guild's emojis, others get a placeholder name
+    pub(crate) fn display(&self, emojis: &HashMap<EmojiId, Emoji>) -> String {
+        match self {
+            EmojiKey::Custom(id) => match emojis.get(id) {
+                Some(emoji) => emoji.to_string(),
+                None => format!("<:emoji:{id}>"),
+            },
+            EmojiKey::Unicode(unicode) => unicode.clone(),
+        }
+    }
+}
+
+/// The guild's emojis by id, from the cache if possible
+pub(crate) async fn guild_emojis(
+    ctx: Context<'_>,
+    guild: GuildId,
+) -> anyhow::Result<HashMap<EmojiId, Emoji>> {
+    if let Some(cached) = ctx.cache().guild(guild) {
+        return Ok(cached.emojis.clone());
+    }
+    Ok(guild
+        .emojis(ctx)
+        .await?
+        .into_iter()
+        .map(|emoji| (emoji.id, emoji))
+        .collect())
+}
+
+/// Errors unless the channel or thread belongs to the guild the command was used in
+pub(crate) async fn check_guild_channel(
+    ctx: Context<'_>,
+    channel: ChannelId,
+) -> anyhow::Result<()> {
+    let guild = ctx.guild_id().expect("guild_only");
+    let in_guild = channel
+        .to_channel(ctx)
+        .await
+        .is_ok_and(|c| c.guild().is_some_and(|c| c.guild_id == guild));
+    if !in_guild {
+        return Err(UserError::err(format!(
+            "{} isn't a channel of this server",
+            channel.mention()
+        )));
+    }
+    Ok(())
+}
+
+/// Split text into parts of at most `limit` bytes, preferably at line breaks
+pub(crate) fn split_text(text: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let end = rest[..end].rfind('\n').filter(|&i| i > 0).unwrap_or(end);
+        parts.push(rest[..end].to_string());
+        rest = rest[end..].trim_start_matches('\n');
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
 /// Split text into multiple messages to stay under Discord's limit
 pub async fn paginate_text(ctx: Context<'_>, lines: &mut VecDeque<String>) -> anyhow::Result<()> {
     let mut s = lines.pop_front().unwrap();
@@ -202,7 +405,7 @@ pub async fn parse_duration_or_date(
 mod tests {
     use super::*;
     use crate::constants::TIMEZONE;
-    use chrono::{Duration, NaiveDate, TimeZone};
+    use chrono::{Duration, NaiveDate};
 
     const NOW: DateTime<Utc> = DateTime::from_timestamp(1, 0).unwrap();
 
@@ -250,6 +453,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_text() {
+        assert_eq!(split_text("short", 10), vec!["short"]);
+        assert_eq!(
+            split_text("line one\nline two", 10),
+            vec!["line one", "line two"]
+        );
+        assert_eq!(split_text("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(split_text("äöü", 3), vec!["ä", "ö", "ü"]);
+    }
+
+    #[test]
+    fn test_format_seconds() {
+        assert_eq!(format_seconds(42), "42s");
+        assert_eq!(format_seconds(60), "1m");
+        assert_eq!(format_seconds(3 * 86400 + 3600 + 59), "3d 1h");
+        assert_eq!(format_seconds(7260), "2h 1m");
+    }
+
+    #[test]
+    fn test_emoji_image_url() {
+        let custom = EmojiKey::Custom(EmojiId::new(123));
+        assert_eq!(custom.image_url(), format!("{EMOJI_URL}123.png?size=64"));
+        let unicode = |emoji: &str| EmojiKey::Unicode(emoji.to_string()).image_url();
+        assert_eq!(unicode("😀"), format!("{TWEMOJI_URL}1f600.png"));
+        assert_eq!(unicode("❤️"), format!("{TWEMOJI_URL}2764.png"));
+        assert_eq!(
+            unicode("🏳️‍🌈"),
+            format!("{TWEMOJI_URL}1f3f3-fe0f-200d-1f308.png")
+        );
+    }
+
     #[tokio::test]
     async fn test_invalid() {
         assert!(parse_duration_or_date(NOW, "invalid").await.is_err());
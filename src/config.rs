@@ -0,0 +1,808 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::read_to_string;
+use std::hash::Hash;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context as _, bail};
+use itertools::Itertools;
+use mini_moka::sync::Cache;
+use poise::serenity_prelude::{
+    ChannelId, Colour, Context, CreateMessage, GuildId, Mentionable, ReactionType, RoleId, UserId,
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::constants::ONE_DAY;
+use crate::llm::LlmConfig;
+use crate::template::{Template, VARIABLES};
+
+pub(crate) const CONFIG_PATH: &str = "assets/config.hjson";
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+const EMBED_TITLE_LIMIT: usize = 256;
+pub(crate) const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+static REGEX_CACHE: LazyLock<Cache<String, Regex>> = LazyLock::new(|| Cache::new(500));
+
+/// Compile a case-insensitive pattern, cached since the rules are checked on every message
+pub(crate) fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    if let Some(regex) = REGEX_CACHE.get(&pattern.to_string()) {
+        return Ok(regex);
+    }
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()?;
+    REGEX_CACHE.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Numbered and named groups of the first match
+fn regex_captures(pattern: &str, content: &str) -> Option<HashMap<String, String>> {
+    let regex = compile_pattern(pattern).ok()?;
+    let captures = regex.captures(content)?;
+    let mut variables = HashMap::new();
+    for (i, name) in regex.capture_names().enumerate() {
+        if let Some(m) = captures.get(i) {
+            variables.insert(i.to_string(), m.as_str().to_string());
+            if let Some(name) = name {
+                variables.insert(name.to_string(), m.as_str().to_string());
+            }
+        }
+    }
+    Some(variables)
+}
+
+/// Start of the first occurrence with a word boundary on both ends, like `\b` in a regex
+fn find_word(content: &str, word: &str) -> Option<usize> {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    content
+        .match_indices(word)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let end = start + word.len();
+            let boundary =
+                |before: Option<char>, after: Option<char>| is_word(before) != is_word(after);
+            boundary(content[..start].chars().next_back(), word.chars().next())
+                && boundary(word.chars().next_back(), content[end..].chars().next())
+        })
+}
+
+#[repr(i16)]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MatchMode {
+    /// anywhere in the message
+    #[default]
+    Substring,
+    /// only whole words, ignoring punctuation around them
+    Word,
+    /// keywords are regular expressions, capture groups can be used in the description
+    Regex,
+}
+
+impl From<i16> for MatchMode {
+    fn from(value: i16) -> Self {
+        match value {
+            1 => MatchMode::Word,
+            2 => MatchMode::Regex,
+            _ => MatchMode::Substring,
+        }
+    }
+}
+
+/// Where and how often an auto reply or reaction may fire
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct RuleScope {
+    /// seconds until the rule fires again in the same channel
+    pub(crate) channel_cooldown: Option<u32>,
+    /// seconds until the rule fires again for the same user
+    pub(crate) user_cooldown: Option<u32>,
+    /// only fire in these channels, all channels if empty
+    pub(crate) allowed_channels: Vec<ChannelId>,
+    pub(crate) denied_channels: Vec<ChannelId>,
+    /// members with any of these roles don't trigger the rule
+    pub(crate) exempt_roles: Vec<RoleId>,
+}
+
+impl RuleScope {
+    pub(crate) fn applies(&self, channel: ChannelId, roles: &[RoleId]) -> bool {
+        (self.allowed_channels.is_empty() || self.allowed_channels.contains(&channel))
+            && !self.denied_channels.contains(&channel)
+            && !roles.iter().any(|role| self.exempt_roles.contains(role))
+    }
+
+    fn validate(&self, what: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        // the cooldown cache forgets everything older than a day
+        let max = ONE_DAY.as_secs();
+        for cooldown in [self.channel_cooldown, self.user_cooldown]
+            .into_iter()
+            .flatten()
+        {
+            if u64::from(cooldown) > max {
+                errors.push(format!(
+                    "{what} cooldown can't be longer than {max} seconds"
+                ));
+            }
+        }
+        errors
+    }
+}
+
+impl Display for RuleScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(cooldown) = self.channel_cooldown {
+            parts.push(format!("{cooldown}s per channel"));
+        }
+        if let Some(cooldown) = self.user_cooldown {
+            parts.push(format!("{cooldown}s per user"));
+        }
+        if !self.allowed_channels.is_empty() {
+            let channels = self.allowed_channels.iter().map(|c| c.mention()).join(" ");
+            parts.push(format!("only in {channels}"));
+        }
+        if !self.denied_channels.is_empty() {
+            let channels = self.denied_channels.iter().map(|c| c.mention()).join(" ");
+            parts.push(format!("not in {channels}"));
+        }
+        if !self.exempt_roles.is_empty() {
+            let roles = self.exempt_roles.iter().map(|r| r.mention()).join(" ");
+            parts.push(format!("ignores {roles}"));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub(crate) struct AutoReply {
+    /// database id, 0 for replies from the config
+    #[serde(skip)]
+    pub(crate) id: i32,
+    pub(crate) keywords: Vec<String>,
+    pub(crate) user: UserId,
+    pub(crate) title: String,
+    pub(crate) description: String,
+    #[serde(default)]
+    pub(crate) ping: bool,
+    #[serde(default)]
+    /// colour as an integer
+    pub(crate) colour: Colour,
+    pub(crate) chance: Option<f64>,
+    #[serde(default)]
+    pub(crate) match_mode: MatchMode,
+    #[serde(flatten)]
+    pub(crate) scope: RuleScope,
+}
+
+impl AutoReply {
+    fn pattern(&self, keyword: &str) -> String {
+        match self.match_mode {
+            MatchMode::Substring => regex::escape(keyword),
+            MatchMode::Word => format!(r"\b{}\b", regex::escape(keyword)),
+            MatchMode::Regex => keyword.to_string(),
+        }
+    }
+
+    /// Match the message against all keywords, the first match returns its keyword and captures:
+    /// `0` is the whole match, followed by numbered and named groups.
+    /// `lowercase` is `content` lowercased once per message, only regex keywords aren't lowercase
+    pub(crate) fn find_match(
+        &self,
+        content: &str,
+        lowercase: &str,
+    ) -> Option<(&str, HashMap<String, String>)> {
+        for keyword in &self.keywords {
+            let found = match self.match_mode {
+                MatchMode::Substring => lowercase.find(keyword.as_str()),
+                MatchMode::Word => find_word(lowercase, keyword),
+                MatchMode::Regex => {
+                    if let Some(variables) = regex_captures(keyword, content) {
+                        return Some((keyword, variables));
+                    }
+                    continue;
+                }
+            };
+            if let Some(start) = found {
+                // lowercasing keeps the offsets unless it changed the length of a character
+                let range = start..start + keyword.len();
+                let matched = if content.len() == lowercase.len() {
+                    content.get(range).unwrap_or(keyword)
+                } else {
+                    keyword
+                };
+                return Some((
+                    keyword,
+                    HashMap::from([("0".to_string(), matched.to_string())]),
+                ));
+            }
+        }
+        None
+    }
+
+    /// Names of the regex groups usable as placeholders
+    fn capture_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for keyword in &self.keywords {
+            if let Ok(regex) = compile_pattern(&self.pattern(keyword)) {
+                for (i, name) in regex.capture_names().enumerate() {
+                    names.push(i.to_string());
+                    names.extend(name.map(str::to_string));
+                }
+            }
+        }
+        names
+    }
+
+    pub(crate) fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.keywords.is_empty() {
+            errors.push(format!("auto reply `{}` has no keywords", self.title));
+        }
+        let captures = self.capture_names();
+        for text in [&self.title, &self.description] {
+            match Template::parse(text) {
+                Ok(template) => {
+                    for variable in template.variables() {
+                        if !VARIABLES.contains(&variable) && !captures.iter().any(|c| c == variable)
+                        {
+                            errors.push(format!(
+                                "auto reply `{}` uses unknown placeholder `{{{variable}}}`",
+                                self.title
+                            ));
+                        }
+                    }
+                }
+                Err(e) => errors.push(format!("auto reply `{}`: {e}", self.title)),
+            }
+        }
+        for keyword in &self.keywords {
+            if self.match_mode == MatchMode::Regex {
+                match compile_pattern(keyword) {
+                    Err(e) => errors.push(format!("auto reply regex `{keyword}` is invalid: {e}")),
+                    Ok(regex) if regex.is_match("") => errors.push(format!(
+                        "auto reply regex `{keyword}` matches every message"
+                    )),
+                    Ok(_) => {}
+                }
+            } else if keyword.to_lowercase() != *keyword {
+                errors.push(format!("auto reply keyword `{keyword}` must be lowercase"));
+            }
+        }
+        if self.chance.is_some_and(|c| !(0.0..=1.0).contains(&c)) {
+            errors.push(format!(
+                "auto reply `{}` chance must be between 0 and 1",
+                self.title
+            ));
+        }
+        if self.title.chars().count() > EMBED_TITLE_LIMIT {
+            errors.push(format!(
+                "auto reply `{}` title is longer than {EMBED_TITLE_LIMIT} characters",
+                self.title
+            ));
+        }
+        if self.description.chars().count() > EMBED_DESCRIPTION_LIMIT {
+            errors.push(format!(
+                "auto reply `{}` description is longer than {EMBED_DESCRIPTION_LIMIT} characters",
+                self.title
+            ));
+        }
+        errors.extend(self.scope.validate(&format!("auto reply `{}`", self.title)));
+        errors
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AutoReaction {
+    pub(crate) keyword: String,
+    pub(crate) emoji: ReactionType,
+    pub(crate) scope: RuleScope,
+}
+
+impl AutoReaction {
+    /// Auto reactions match whole words of the lowercase message
+    pub(crate) fn validate(&self) -> Vec<String> {
+        let keyword = &self.keyword;
+        let mut errors = Vec::new();
+        if keyword.is_empty() || keyword.chars().any(|c| !c.is_alphanumeric() && c != '_') {
+            errors.push(format!("auto reaction `{keyword}` must be a single word"));
+        }
+        if keyword.to_lowercase() != *keyword {
+            errors.push(format!("auto reaction `{keyword}` must be lowercase"));
+        }
+        errors.extend(self.scope.validate(&format!("auto reaction `{keyword}`")));
+        errors
+    }
+}
+
+/// Auto reactions in the config are either just the emoji or the emoji with a scope
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigReaction {
+    Scoped {
+        emoji: ReactionType,
+        #[serde(flatten)]
+        scope: RuleScope,
+    },
+    Emoji(ReactionType),
+}
+
+fn deserialize_reactions<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<AutoReaction>, D::Error> {
+    let reactions = HashMap::<String, ConfigReaction>::deserialize(deserializer)?;
+    Ok(reactions
+        .into_iter()
+        .map(|(keyword, reaction)| {
+            let (emoji, scope) = match reaction {
+                ConfigReaction::Scoped { emoji, scope } => (emoji, scope),
+                ConfigReaction::Emoji(emoji) => (emoji, RuleScope::default()),
+            };
+            AutoReaction {
+                keyword,
+                emoji,
+                scope,
+            }
+        })
+        .sorted_by(|a, b| a.keyword.cmp(&b.keyword))
+        .collect())
+}
+
+/// The link fix for this domain applies to every link
+pub(crate) const ALL_DOMAINS: &str = "*";
+
+/// How links of a domain are fixed, applied after the fix for [ALL_DOMAINS]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct LinkFix {
+    pub(crate) host: Option<String>,
+    /// query parameters to remove, `*` matches anything like in `utm_*`
+    #[serde(deserialize_with = "one_or_many")]
+    pub(crate) tracking: Vec<String>,
+    /// case-insensitive regex replacements of the path, in order
+    pub(crate) paths: Vec<PathRewrite>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub(crate) struct PathRewrite {
+    pub(crate) pattern: String,
+    /// may refer to groups of the pattern like `$1`
+    pub(crate) replacement: String,
+}
+
+impl LinkFix {
+    pub(crate) fn validate(&self, domain: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        if domain == ALL_DOMAINS && self.host.is_some() {
+            errors.push("link fix for all domains can't replace the host".to_string());
+        }
+        for rewrite in &self.paths {
+            if let Err(e) = compile_pattern(&rewrite.pattern) {
+                errors.push(format!(
+                    "link fix {domain} has an invalid path pattern: {e}"
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// Accept a single string where a list is expected
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct CeleryConfig {
+    pub(crate) prompt: String,
+    pub(crate) chance: f64,
+    pub(crate) cooldown: u64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Config {
+    #[cfg(feature = "activity")]
+    #[serde(default)]
+    pub(crate) active_guilds: Vec<GuildId>,
+    /// poll the config file for changes, only read at startup
+    #[serde(default)]
+    pub(crate) watch_config: bool,
+    /// only read at startup
+    #[serde(default)]
+    pub(crate) llm: LlmConfig,
+    #[serde(default)]
+    event_channel_per_guild: HashMap<GuildId, ChannelId>,
+    #[serde(default)]
+    link_fixes: HashMap<String, LinkFix>,
+    #[serde(default, deserialize_with = "deserialize_reactions")]
+    auto_reactions: Vec<AutoReaction>,
+    #[serde(default)]
+    auto_replies: Vec<AutoReply>,
+    #[serde(default)]
+    entry_sounds: HashMap<UserId, String>,
+    #[serde(default)]
+    celery: HashMap<ChannelId, CeleryConfig>,
+}
+
+impl Config {
+    /// Read and validate the config file, a missing file is an empty config
+    pub(crate) fn load() -> anyhow::Result<Config> {
+        let config_data = read_to_string(CONFIG_PATH).unwrap_or_default();
+        Self::parse(&config_data)
+    }
+
+    fn parse(config_data: &str) -> anyhow::Result<Config> {
+        let mut config: Config =
+            deser_hjson::from_str(config_data).context("Failed to parse config")?;
+        config.skip_invalid_replies();
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Skipped like invalid stored replies, so braces from before the templates don't stop the bot
+    fn skip_invalid_replies(&mut self) {
+        self.auto_replies.retain(|reply| {
+            let errors = reply.validate();
+            if !errors.is_empty() {
+                warn!(?errors, "Skipping invalid auto reply from the config");
+            }
+            errors.is_empty()
+        });
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+        for reaction in &self.auto_reactions {
+            errors.extend(reaction.validate());
+        }
+        for (domain, fix) in &self.link_fixes {
+            errors.extend(fix.validate(domain));
+        }
+        for (channel, celery) in &self.celery {
+            if !(0.0..=1.0).contains(&celery.chance) {
+                errors.push(format!("celery {channel} chance must be between 0 and 1"));
+            }
+        }
+        for (user, sound) in &self.entry_sounds {
+            if !Path::new(sound).is_file() {
+                errors.push(format!("entry sound {sound} for {user} doesn't exist"));
+            }
+        }
+        if !errors.is_empty() {
+            bail!("Invalid config:\n{}", errors.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+/// Marks config changes that only reach the servers WoBot joins from now on
+pub(crate) const NEW_SERVERS_ONLY: &str = " (new servers only)";
+
+/// The part of the config that can be swapped while the bot is running,
+/// everything but the entry sounds only seeds the settings of new guilds
+#[derive(Debug, Default)]
+pub(crate) struct Settings {
+    pub(crate) event_channel_per_guild: HashMap<GuildId, ChannelId>,
+    pub(crate) link_fixes: HashMap<String, LinkFix>,
+    pub(crate) auto_reactions: Vec<AutoReaction>,
+    pub(crate) auto_replies: Vec<AutoReply>,
+    pub(crate) entry_sounds: HashMap<UserId, String>,
+    pub(crate) celery: HashMap<ChannelId, CeleryConfig>,
+}
+
+impl From<Config> for Settings {
+    fn from(config: Config) -> Self {
+        Settings {
+            event_channel_per_guild: config.event_channel_per_guild,
+            link_fixes: config.link_fixes,
+            auto_reactions: config.auto_reactions,
+            auto_replies: config.auto_replies,
+            entry_sounds: config.entry_sounds,
+            celery: config.celery,
+        }
+    }
+}
+
+impl Settings {
+    /// List what changed from `self` to `new`, one line per added (+), removed (-) or changed (~) entry,
+    /// changes to the per server settings are marked with [NEW_SERVERS_ONLY]
+    pub(crate) fn diff(&self, new: &Settings) -> Vec<String> {
+        let mut changes = Vec::new();
+        diff_maps(
+            "entry sound",
+            &self.entry_sounds,
+            &new.entry_sounds,
+            &mut changes,
+        );
+        let mut seeded = Vec::new();
+        diff_maps(
+            "event channel",
+            &self.event_channel_per_guild,
+            &new.event_channel_per_guild,
+            &mut seeded,
+        );
+        diff_maps("link fix", &self.link_fixes, &new.link_fixes, &mut seeded);
+        diff_maps(
+            "auto reaction",
+            &self
+                .auto_reactions
+                .iter()
+                .map(|r| (&r.keyword, r))
+                .collect(),
+            &new.auto_reactions.iter().map(|r| (&r.keyword, r)).collect(),
+            &mut seeded,
+        );
+        diff_maps(
+            "auto reply",
+            &by_keyword(&self.auto_replies),
+            &by_keyword(&new.auto_replies),
+            &mut seeded,
+        );
+        diff_maps("celery", &self.celery, &new.celery, &mut seeded);
+        changes.extend(seeded.into_iter().map(|c| c + NEW_SERVERS_ONLY));
+        changes
+    }
+}
+
+fn by_keyword(replies: &[AutoReply]) -> HashMap<String, &AutoReply> {
+    replies
+        .iter()
+        .map(|r| (r.keywords.first().cloned().unwrap_or_default(), r))
+        .collect()
+}
+
+fn diff_maps<K: Display + Ord + Hash, V: PartialEq>(
+    what: &str,
+    old: &HashMap<K, V>,
+    new: &HashMap<K, V>,
+    changes: &mut Vec<String>,
+) {
+    for key in old.keys().chain(new.keys()).unique().sorted() {
+        match (old.get(key), new.get(key)) {
+            (Some(_), None) => changes.push(format!("- {what} {key}")),
+            (None, Some(_)) => changes.push(format!("+ {what} {key}")),
+            (Some(a), Some(b)) if a != b => changes.push(format!("~ {what} {key}")),
+            _ => {}
+        }
+    }
+}
+
+/// Shared handle to the current [Settings]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LiveConfig(Arc<RwLock<Arc<Settings>>>);
+
+impl LiveConfig {
+    pub(crate) fn new(settings: Settings) -> Self {
+        LiveConfig(Arc::new(RwLock::new(Arc::new(settings))))
+    }
+
+    pub(crate) fn get(&self) -> Arc<Settings> {
+        self.0.read().expect("config").clone()
+    }
+
+    /// Parse the config file again and swap it in, the old settings stay active on errors
+    pub(crate) fn reload(&self) -> anyhow::Result<Vec<String>> {
+        let new = Settings::from(Config::load()?);
+        let mut current = self.0.write().expect("config");
+        let changes = current.diff(&new);
+        *current = Arc::new(new);
+        Ok(changes)
+    }
+}
+
+fn last_modified() -> Option<SystemTime> {
+    std::fs::metadata(CONFIG_PATH)
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+pub(crate) fn watch_config(ctx: Context, config: LiveConfig) {
+    tokio::spawn(async move {
+        let mut interval = interval(WATCH_INTERVAL);
+        let mut modified = last_modified();
+
+        loop {
+            interval.tick().await;
+
+            let new_modified = last_modified();
+            if new_modified == modified {
+                continue;
+            }
+            modified = new_modified;
+
+            match config.reload() {
+                Ok(changes) => info!(?changes, "Reloaded config"),
+                Err(err) => {
+                    error!(error = ?err, "Failed reloading config");
+                    let message = format!("Config reload failed, keeping the old one:\n{err:#}");
+                    if let Err(e) = notify_owner(&ctx, message).await {
+                        error!(error = ?e, "Failed notifying owner");
+                    }
+                }
+            }
+        }
+    });
+    info!("Started config watcher");
+}
+
+async fn notify_owner(ctx: &Context, message: String) -> anyhow::Result<()> {
+    let info = ctx.http.get_current_application_info().await?;
+    if let Some(owner) = info.owner {
+        owner
+            .direct_message(ctx, CreateMessage::new().content(message))
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+auto_reactions: {
+  robot: {
+    name: "🤖"
+  }
+}
+auto_replies: [
+  {
+    keywords: ["wobot info"]
+    user: 1
+    title: About WoBot
+    description: "Hi"
+  }
+]
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(CONFIG).unwrap();
+        assert_eq!(config.auto_replies.len(), 1);
+        assert!(Config::parse("").is_ok());
+    }
+
+    #[test]
+    fn test_reject_invalid_config() {
+        assert!(Config::parse("auto_reactions: {").is_err());
+        assert!(Config::parse(&CONFIG.replace("robot", "\"two words\"")).is_err());
+        // invalid auto replies are skipped instead
+        for invalid in [
+            CONFIG.replace("wobot info", "WoBot info"),
+            CONFIG.replace("\"Hi\"", "\"{count}\""),
+        ] {
+            assert!(Config::parse(&invalid).unwrap().auto_replies.is_empty());
+        }
+        assert!(Config::parse(&CONFIG.replace("\"Hi\"", "\"{author} {0}\"")).is_ok());
+    }
+
+    #[test]
+    fn test_parse_link_fixes() {
+        let config = Config::parse(
+            r#"link_fixes: {
+  instagram.com: {tracking: "igsh"}
+  "*": {tracking: ["utm_*", "fbclid"]}
+  open.spotify.com: {paths: [{pattern: "^/intl-[a-z]+/", replacement: "/"}]}
+}"#,
+        )
+        .unwrap();
+        assert_eq!(config.link_fixes["instagram.com"].tracking, vec!["igsh"]);
+        assert_eq!(config.link_fixes[ALL_DOMAINS].tracking.len(), 2);
+        assert_eq!(config.link_fixes["open.spotify.com"].paths.len(), 1);
+        assert!(Config::parse(r#"link_fixes: {"*": {host: "example.com"}}"#).is_err());
+        assert!(
+            Config::parse(r#"link_fixes: {a.com: {paths: [{pattern: "(", replacement: ""}]}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Settings::from(Config::parse(CONFIG).unwrap());
+        let new_config = CONFIG
+            .replace("robot", "bot")
+            .replace("\"Hi\"", "\"Hello\"");
+        let new = Settings::from(Config::parse(&new_config).unwrap());
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                "+ auto reaction bot (new servers only)",
+                "- auto reaction robot (new servers only)",
+                "~ auto reply wobot info (new servers only)"
+            ]
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_parse_scopes() {
+        let config = Config::parse(
+            r#"
+auto_reactions: {
+  robot: {
+    emoji: {
+      name: "🤖"
+    }
+    user_cooldown: 60
+    denied_channels: ["2"]
+  }
+  plain: {
+    name: "🤖"
+  }
+}
+auto_replies: [
+  {
+    keywords: ["wobot info"]
+    user: 1
+    title: About WoBot
+    description: "Hi"
+    channel_cooldown: 30
+    allowed_channels: [1]
+    exempt_roles: [3]
+  }
+]
+"#,
+        )
+        .unwrap();
+        let [plain, robot] = &config.auto_reactions[..] else {
+            panic!("expected two reactions");
+        };
+        assert_eq!(plain.scope, RuleScope::default());
+        assert_eq!(robot.scope.user_cooldown, Some(60));
+        assert!(!robot.scope.applies(ChannelId::new(2), &[]));
+
+        let scope = &config.auto_replies[0].scope;
+        assert_eq!(scope.channel_cooldown, Some(30));
+        assert!(scope.applies(ChannelId::new(1), &[RoleId::new(4)]));
+        assert!(!scope.applies(ChannelId::new(1), &[RoleId::new(3)]));
+        assert!(!scope.applies(ChannelId::new(2), &[]));
+
+        let too_long = CONFIG.replace("user: 1", "user: 1\nuser_cooldown: 100000");
+        assert!(Config::parse(&too_long).unwrap().auto_replies.is_empty());
+    }
+
+    #[test]
+    fn test_match_modes() {
+        let find_match = |reply: &AutoReply, content: &str| {
+            let (keyword, captures) = reply.find_match(content, &content.to_lowercase())?;
+            Some((keyword.to_string(), captures))
+        };
+        let mut reply = Config::parse(CONFIG).unwrap().auto_replies.remove(0);
+        let (_, captures) = find_match(&reply, "WoBot Information").unwrap();
+        assert_eq!(captures["0"], "WoBot Info");
+
+        reply.match_mode = MatchMode::Word;
+        assert!(find_match(&reply, "wobot information").is_none());
+        assert!(find_match(&reply, "what is wobot info?").is_some());
+        assert!(find_match(&reply, "wobot info_ wobot info").is_some());
+        assert!(find_match(&reply, "wobot info_").is_none());
+
+        reply.match_mode = MatchMode::Regex;
+        reply.keywords = vec![r"wobot (?<cmd>\w+) (\d+)".to_string()];
+        let (_, captures) = find_match(&reply, "hey WoBot roll 20").unwrap();
+        assert_eq!(captures["0"], "WoBot roll 20");
+        assert_eq!(captures["cmd"], "roll");
+        assert_eq!(captures["2"], "20");
+
+        reply.keywords = vec!["(unclosed".to_string()];
+        assert!(!reply.validate().is_empty());
+        reply.keywords = vec![".*".to_string()];
+        assert!(!reply.validate().is_empty());
+    }
+}
@@ -10,6 +10,7 @@ use std::time::Duration;
 use tracing::info;
 
 pub(crate) const TIMEZONE: Tz = Berlin;
+pub(crate) const DATE_FORMAT: &str = "%d.%m.%Y";
 pub(crate) const ONE_HOUR: Duration = Duration::from_secs(60 * 60);
 pub(crate) const ONE_DAY: Duration = Duration::from_secs(24 * 60 * 60);
 
@@ -28,7 +29,13 @@ static FONT_DATA: LazyLock<Vec<u8>> = LazyLock::new(|| {
 pub(crate) static FONT: LazyLock<FontRef> =
     LazyLock::new(|| FontRef::try_from_slice(FONT_DATA.as_slice()).expect("Failed to parse font"));
 
-static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+pub(crate) const EMOJI_URL: &str = "https://cdn.discordapp.com/emojis/";
+/// Images of Unicode emojis in the style Discord shows them
+pub(crate) const TWEMOJI_URL: &str =
+    "https://cdn.jsdelivr.net/gh/jdecked/twemoji@v15.1.0/assets/72x72/";
+
+pub(crate) static USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
 pub(crate) static HTTP_CLIENT: LazyLock<ReqwestClient> = LazyLock::new(|| {
     ReqwestClient::builder()
@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+
+use mini_moka::sync::Cache;
+use poise::serenity_prelude::{ChannelId, Colour, Guild, GuildId, ReactionType, UserId};
+use sqlx::{PgPool, query};
+use tracing::{info, warn};
+
+use crate::config::{
+    AutoReaction, AutoReply, CeleryConfig, LinkFix, MatchMode, PathRewrite, RuleScope, Settings,
+};
+use crate::constants::ONE_HOUR;
+
+/// Rules of a single guild, stored in the database
+#[derive(Debug, Default)]
+pub(crate) struct GuildSettings {
+    pub(crate) event_channel: Option<ChannelId>,
+    pub(crate) link_fixes: HashMap<String, LinkFix>,
+    /// fix links in every message, not just with `/embed`
+    pub(crate) auto_link_fix: bool,
+    /// repost fixed links with the author's name and avatar
+    pub(crate) link_fix_webhook: bool,
+    /// channels without automatic link fixes
+    pub(crate) link_fix_opt_outs: HashSet<ChannelId>,
+    pub(crate) auto_reactions: Vec<AutoReaction>,
+    pub(crate) auto_replies: Vec<AutoReply>,
+    /// stored auto replies that are invalid and never sent, with why, they can still be edited
+    pub(crate) skipped_replies: Vec<(AutoReply, Vec<String>)>,
+    pub(crate) celery: HashMap<ChannelId, CeleryConfig>,
+}
+
+static GUILD_SETTINGS_CACHE: LazyLock<Cache<GuildId, Arc<GuildSettings>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(100)
+        .time_to_idle(ONE_HOUR)
+        .build()
+});
+
+pub(crate) async fn get_guild_settings(
+    database: &PgPool,
+    guild: GuildId,
+) -> anyhow::Result<Arc<GuildSettings>> {
+    if let Some(settings) = GUILD_SETTINGS_CACHE.get(&guild) {
+        return Ok(settings);
+    }
+
+    let settings = Arc::new(load_guild_settings(database, guild).await?);
+    GUILD_SETTINGS_CACHE.insert(guild, settings.clone());
+    Ok(settings)
+}
+
+/// Scope columns as they are stored in `guild_auto_replies` and `guild_auto_reactions`
+pub(crate) struct ScopeColumns {
+    pub(crate) channel_cooldown: Option<i32>,
+    pub(crate) user_cooldown: Option<i32>,
+    pub(crate) allowed_channels: Vec<i64>,
+    pub(crate) denied_channels: Vec<i64>,
+    pub(crate) exempt_roles: Vec<i64>,
+}
+
+impl From<&RuleScope> for ScopeColumns {
+    fn from(scope: &RuleScope) -> Self {
+        ScopeColumns {
+            channel_cooldown: scope.channel_cooldown.map(|c| c as i32),
+            user_cooldown: scope.user_cooldown.map(|c| c as i32),
+            allowed_channels: scope.allowed_channels.iter().map(|&c| c.into()).collect(),
+            denied_channels: scope.denied_channels.iter().map(|&c| c.into()).collect(),
+            exempt_roles: scope.exempt_roles.iter().map(|&r| r.into()).collect(),
+        }
+    }
+}
+
+impl From<ScopeColumns> for RuleScope {
+    fn from(columns: ScopeColumns) -> Self {
+        RuleScope {
+            channel_cooldown: columns.channel_cooldown.map(|c| c as u32),
+            user_cooldown: columns.user_cooldown.map(|c| c as u32),
+            allowed_channels: ids(columns.allowed_channels),
+            denied_channels: ids(columns.denied_channels),
+            exempt_roles: ids(columns.exempt_roles),
+        }
+    }
+}
+
+fn ids<T: From<u64>>(ids: Vec<i64>) -> Vec<T> {
+    ids.into_iter().map(|id| T::from(id as u64)).collect()
+}
+
+/// Call after changing a guild's settings in the database
+pub(crate) fn invalidate_guild_settings(guild: GuildId) {
+    GUILD_SETTINGS_CACHE.invalidate(&guild);
+}
+
+async fn load_guild_settings(database: &PgPool, guild: GuildId) -> anyhow::Result<GuildSettings> {
+    let guild_id = guild.get() as i64;
+    let row = query!(
+        "SELECT event_channel_id, auto_link_fix, link_fix_webhook FROM guild_settings WHERE guild_id = $1",
+        guild_id
+    )
+    .fetch_optional(database)
+    .await?;
+    let event_channel = row
+        .as_ref()
+        .and_then(|row| row.event_channel_id)
+        .map(|id| ChannelId::new(id as u64));
+    let auto_link_fix = row.as_ref().is_some_and(|row| row.auto_link_fix);
+    let link_fix_webhook = row.as_ref().is_some_and(|row| row.link_fix_webhook);
+
+    let link_fix_opt_outs = query!(
+        "SELECT channel_id FROM guild_link_fix_opt_outs WHERE guild_id = $1",
+        guild_id
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| ChannelId::new(row.channel_id as u64))
+    .collect();
+
+    let link_fixes = query!(
+        "SELECT domain, host, tracking, path_patterns, path_replacements FROM guild_link_fixes WHERE guild_id = $1",
+        guild_id
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let paths = row
+            .path_patterns
+            .into_iter()
+            .zip(row.path_replacements)
+            .map(|(pattern, replacement)| PathRewrite {
+                pattern,
+                replacement,
+            })
+            .collect();
+        let fix = LinkFix {
+            host: row.host,
+            tracking: row.tracking,
+            paths,
+        };
+        (row.domain, fix)
+    })
+    .collect();
+
+    let mut auto_reactions = Vec::new();
+    for row in query!(
+        "SELECT * FROM guild_auto_reactions WHERE guild_id = $1 ORDER BY keyword",
+        guild_id
+    )
+    .fetch_all(database)
+    .await?
+    {
+        let emoji = match ReactionType::try_from(row.emoji.as_str()) {
+            Ok(emoji) => emoji,
+            Err(error) => {
+                warn!(?error, guild = ?guild, "Skipping auto reaction with invalid emoji {}", row.emoji);
+                continue;
+            }
+        };
+        auto_reactions.push(AutoReaction {
+            keyword: row.keyword,
+            emoji,
+            scope: RuleScope::from(ScopeColumns {
+                channel_cooldown: row.channel_cooldown,
+                user_cooldown: row.user_cooldown,
+                allowed_channels: row.allowed_channels,
+                denied_channels: row.denied_channels,
+                exempt_roles: row.exempt_roles,
+            }),
+        });
+    }
+
+    let mut auto_replies = Vec::new();
+    let mut skipped_replies = Vec::new();
+    for row in query!(
+        "SELECT * FROM guild_auto_replies WHERE guild_id = $1 ORDER BY id",
+        guild_id
+    )
+    .fetch_all(database)
+    .await?
+    {
+        let reply = AutoReply {
+            id: row.id,
+            keywords: row.keywords,
+            user: UserId::new(row.user_id as u64),
+            title: row.title,
+            description: row.description,
+            ping: row.ping,
+            colour: Colour::new(row.colour as u32),
+            chance: row.chance,
+            match_mode: MatchMode::from(row.match_mode),
+            scope: RuleScope::from(ScopeColumns {
+                channel_cooldown: row.channel_cooldown,
+                user_cooldown: row.user_cooldown,
+                allowed_channels: row.allowed_channels,
+                denied_channels: row.denied_channels,
+                exempt_roles: row.exempt_roles,
+            }),
+        };
+        let errors = reply.validate();
+        if errors.is_empty() {
+            auto_replies.push(reply);
+        } else {
+            warn!(?errors, guild = ?guild, "Skipping invalid auto reply {}", reply.id);
+            skipped_replies.push((reply, errors));
+        }
+    }
+
+    let celery = query!(
+        "SELECT channel_id, prompt, chance, cooldown FROM guild_celery WHERE guild_id = $1",
+        guild_id
+    )
+    .fetch_all(database)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let celery = CeleryConfig {
+            prompt: row.prompt,
+            chance: row.chance,
+            cooldown: row.cooldown as u64,
+        };
+        (ChannelId::new(row.channel_id as u64), celery)
+    })
+    .collect();
+
+    Ok(GuildSettings {
+        event_channel,
+        link_fixes,
+        auto_link_fix,
+        link_fix_webhook,
+        link_fix_opt_outs,
+        auto_reactions,
+        auto_replies,
+        skipped_replies,
+        celery,
+    })
+}
+
+/// Copy the config defaults into the database the first time we see a guild
+pub(crate) async fn seed_guild_settings(
+    database: &PgPool,
+    guild: &Guild,
+    defaults: &Settings,
+) -> anyhow::Result<()> {
+    let guild_id = guild.id.get() as i64;
+    let event_channel = defaults
+        .event_channel_per_guild
+        .get(&guild.id)
+        .map(|c| c.get() as i64);
+
+    let mut transaction = database.begin().await?;
+    let inserted = query!(
+        "INSERT INTO guild_settings (guild_id, event_channel_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        guild_id,
+        event_channel
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Ok(());
+    }
+
+    for (domain, fix) in &defaults.link_fixes {
+        let (patterns, replacements): (Vec<_>, Vec<_>) = fix
+            .paths
+            .iter()
+            .map(|p| (p.pattern.clone(), p.replacement.clone()))
+            .unzip();
+        query!(
+            "INSERT INTO guild_link_fixes (guild_id, domain, host, tracking, path_patterns, path_replacements)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+            guild_id,
+            domain,
+            fix.host,
+            &fix.tracking,
+            &patterns,
+            &replacements
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    for reaction in &defaults.auto_reactions {
+        let scope = ScopeColumns::from(&reaction.scope);
+        query!(
+            "INSERT INTO guild_auto_reactions (guild_id, keyword, emoji, channel_cooldown, user_cooldown, allowed_channels, denied_channels, exempt_roles)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            guild_id,
+            reaction.keyword,
+            reaction.emoji.to_string(),
+            scope.channel_cooldown,
+            scope.user_cooldown,
+            &scope.allowed_channels,
+            &scope.denied_channels,
+            &scope.exempt_roles
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    for reply in &defaults.auto_replies {
+        let scope = ScopeColumns::from(&reply.scope);
+        query!(
+            "INSERT INTO guild_auto_replies (guild_id, keywords, user_id, title, description, ping, colour, chance, match_mode,
+                                             channel_cooldown, user_cooldown, allowed_channels, denied_channels, exempt_roles)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+            guild_id,
+            &reply.keywords,
+            reply.user.get() as i64,
+            reply.title,
+            reply.description,
+            reply.ping,
+            reply.colour.0 as i32,
+            reply.chance,
+            reply.match_mode as i16,
+            scope.channel_cooldown,
+            scope.user_cooldown,
+            &scope.allowed_channels,
+            &scope.denied_channels,
+            &scope.exempt_roles
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    // celery is configured per channel, only take the ones from this guild
+    for (channel, celery) in &defaults.celery {
+        if !guild.channels.contains_key(channel) {
+            continue;
+        }
+        query!(
+            "INSERT INTO guild_celery (channel_id, guild_id, prompt, chance, cooldown) VALUES ($1, $2, $3, $4, $5)",
+            channel.get() as i64,
+            guild_id,
+            celery.prompt,
+            celery.chance,
+            celery.cooldown as i64
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+
+    invalidate_guild_settings(guild.id);
+    info!("Seeded settings for guild {}", guild.id);
+    Ok(())
+}
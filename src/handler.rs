@@ -1,26 +1,51 @@
 #[cfg(feature = "activity")]
-use crate::CacheEntry;
-use crate::commands::{change_reaction_role, track_emoji_usage, track_song};
-use crate::constants::HTTP_CLIENT;
+use crate::activity::{sync_voice_sessions, track_voice, update_activity};
+use crate::celery::celery_fact;
+#[cfg(feature = "activity")]
+use crate::commands::ActivityKind;
+use crate::commands::utils::today;
+use crate::commands::{
+    answer_in_thread, change_reaction_role, track_emoji_usage, track_message_emojis, track_song,
+};
+use crate::config::{AutoReply, RuleScope};
+use crate::constants::{DATE_FORMAT, ONE_DAY};
+use crate::easy_embed::EasyEmbed;
+use crate::guild_settings::{GuildSettings, get_guild_settings, seed_guild_settings};
+use crate::link_fix::auto_fix_links;
+use crate::template::Template;
 use crate::{Data, UserError};
-use anyhow::anyhow;
+use anyhow::Context as _;
 use itertools::Itertools;
-use poise::serenity_prelude::json::json;
+use mini_moka::sync::Cache;
 use poise::serenity_prelude::*;
 use poise::{CreateReply, FrameworkContext};
 use rand::random_bool;
 use regex::Regex;
 use songbird::input::File;
-use sqlx::query;
+use sqlx::{PgPool, query};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::LazyLock;
-use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 use tracing::error;
-#[cfg(feature = "activity")]
-use tracing::warn;
 
 static WORD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b\w+\b").unwrap());
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Rule {
+    Reply(i32),
+    Reaction(GuildId, String),
+}
+
+/// When a rule last fired per channel or user id, snowflakes never collide between the two.
+/// Cooldowns are capped at a day so older entries can go.
+static COOLDOWNS: LazyLock<Cache<(Rule, u64), Instant>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(10_000)
+        .time_to_live(ONE_DAY)
+        .build()
+});
+
 pub(crate) async fn event_handler(
     ctx: &Context,
     event: &FullEvent,
@@ -30,13 +55,25 @@ pub(crate) async fn event_handler(
     match event {
         FullEvent::VoiceStateUpdate { new, old } => {
             if let Some(guild) = new.guild_id {
+                #[cfg(feature = "activity")]
+                if new.member.as_ref().is_none_or(|m| !m.user.bot)
+                    // mutes keep the channel, without a cached old state the session restarts
+                    && old.as_ref().is_none_or(|old| old.channel_id != new.channel_id)
+                {
+                    if let Some(channel) = new.channel_id {
+                        update_activity(data, guild, channel, new.user_id, ActivityKind::VoiceJoin);
+                    }
+                    // the entry sound plays anyway
+                    if let Err(e) = track_voice(data, guild, new.user_id, new.channel_id).await {
+                        error!(error = ?e, ?guild, "Failed to track voice session");
+                    }
+                }
                 let switched_channel = old.as_ref().is_some_and(|old| old.channel_id.is_some());
-                let user_has_entry_sound = data.entry_sounds.contains_key(&new.user_id);
+                let entry_sound = data.config.get().entry_sounds.get(&new.user_id).cloned();
                 if let Some(channel) = new.channel_id
                     && !switched_channel
-                    && user_has_entry_sound
+                    && let Some(sound) = entry_sound
                 {
-                    let sound = data.entry_sounds.get(&new.user_id).unwrap().clone();
                     let file = File::new(PathBuf::from(sound));
                     let manager = songbird::get(ctx).await.expect("Songbird initialized");
                     let handler_lock = manager.join(guild, channel).await?;
@@ -44,23 +81,25 @@ pub(crate) async fn event_handler(
                     let song = handler.play_input(file.into());
                     track_song(manager, guild, song)?;
                 }
-                #[cfg(feature = "activity")]
-                update_activity(data, guild, new.user_id).await;
             }
             Ok(())
         }
         FullEvent::ReactionAdd { add_reaction } => {
+            #[cfg(feature = "activity")]
+            if let Some(guild) = add_reaction.guild_id
+                && let Some(user) = add_reaction.user_id
+            {
+                update_activity(
+                    data,
+                    guild,
+                    add_reaction.channel_id,
+                    user,
+                    ActivityKind::Reaction,
+                );
+            }
             let result = tokio::join!(
                 track_emoji_usage(data, add_reaction, true),
                 change_reaction_role(ctx, data, add_reaction, true),
-                async {
-                    #[cfg(feature = "activity")]
-                    if let Some(guild) = add_reaction.guild_id
-                        && let Some(user) = add_reaction.user_id
-                    {
-                        update_activity(data, guild, user).await
-                    }
-                }
             );
             result.0.and(result.1)
         }
@@ -75,105 +114,89 @@ pub(crate) async fn event_handler(
             if new_message.author.bot {
                 return Ok(());
             }
+            let Some(guild) = new_message.guild_id else {
+                return Ok(());
+            };
+            #[cfg(feature = "activity")]
+            update_activity(
+                data,
+                guild,
+                new_message.channel_id,
+                new_message.author.id,
+                ActivityKind::Message,
+            );
+            let settings = get_guild_settings(&data.database, guild).await?;
             let content = new_message.content.to_lowercase();
             let result = tokio::join!(
-                auto_react(ctx, data, new_message, &content),
-                auto_reply(ctx, data, new_message, &content),
-                celery_fact(ctx, data, new_message.channel_id),
-                async {
-                    #[cfg(feature = "activity")]
-                    if let Some(guild) = new_message.guild_id {
-                        update_activity(data, guild, new_message.author.id).await;
-                    }
-                },
-                async {
-                    #[cfg(feature = "activity")]
-                    if let Some(guild) = new_message.guild_id {
-                        update_message_count(data, guild, new_message.author.id).await;
-                    }
-                }
+                auto_react(ctx, guild, &settings, new_message, &content),
+                auto_reply(ctx, data, guild, &settings, new_message, &content),
+                celery_fact(ctx, data, &settings, new_message.channel_id),
+                answer_in_thread(ctx, data, new_message),
+                auto_fix_links(ctx, data, &settings, new_message),
+                track_message_emojis(data, guild, new_message),
             );
-            result.0.and(result.1).and(result.2)
+            result
+                .0
+                .and(result.1)
+                .and(result.2)
+                .and(result.3)
+                .and(result.4)
+                .and(result.5)
         }
-        _ => Ok(()),
-    }
-}
-
-#[cfg(feature = "activity")]
-async fn update_message_count(data: &Data, guild: GuildId, user: UserId) {
-    if !data.activity_per_guild.contains_key(&guild) {
-        return;
-    }
-    let result = query!("UPDATE activity SET message_count = message_count + 1 WHERE user_id = $1 AND guild_id = $2", user.get() as i64, guild.get() as i64)
-        .execute(&data.database)
-        .await;
-    if let Err(e) = result {
-        warn!("Failed to update message count for {}: {}", user.get(), e);
-    }
-}
-
-#[cfg(feature = "activity")]
-async fn update_activity(data: &Data, guild: GuildId, user: UserId) {
-    if let Some(guild_activity) = data.activity_per_guild.get(&guild)
-        && guild_activity.get(&user).is_none()
-    {
-        let result = query!("INSERT INTO activity (user_id, guild_id) VALUES ($1, $2) ON CONFLICT (user_id, guild_id) DO UPDATE SET last_active = now()", user.get() as i64, guild.get() as i64)
-                .execute(&data.database)
-                .await;
-        if let Err(e) = result {
-            warn!("Failed to update activity for {}: {}", user.get(), e);
-        } else {
-            guild_activity.insert(user, CacheEntry {});
+        FullEvent::GuildCreate { guild, .. } => {
+            #[cfg(feature = "activity")]
+            if let Err(e) = sync_voice_sessions(data, guild).await {
+                error!(error = ?e, guild = ?guild.id, "Failed to sync voice sessions");
+            }
+            seed_guild_settings(&data.database, guild, &data.config.get()).await
         }
+        _ => Ok(()),
     }
 }
 
 async fn auto_reply(
     ctx: &Context,
     data: &Data,
+    guild: GuildId,
+    settings: &GuildSettings,
     new_message: &Message,
     content: &str,
 ) -> anyhow::Result<()> {
-    let matches = data
+    let matches = settings
         .auto_replies
         .iter()
-        .filter(|r| r.keywords.iter().any(|s| content.contains(s)));
+        .filter_map(|r| Some((r, r.find_match(&new_message.content, content)?)));
 
-    for reply in matches {
+    for (reply, (keyword, captures)) in matches {
         if reply.chance.is_some_and(|chance| random_bool(chance)) {
             continue;
         }
+        if !may_fire(Rule::Reply(reply.id), &reply.scope, new_message) {
+            continue;
+        }
 
-        let keyword = reply.keywords.first().unwrap();
-        query!("INSERT INTO auto_replies(user_id, keyword, count) VALUES ($1, $2, 1) ON CONFLICT (keyword, user_id) DO UPDATE SET count = auto_replies.count + 1", new_message.author.id.get() as i64, keyword).execute(&data.database).await?;
-        let stats = query!(
-                        "SELECT SUM(count)::int AS count FROM auto_replies WHERE keyword ILIKE '%' || $1 || '%'",
-                        keyword
-                    )
-            .fetch_one(&data.database)
-            .await?;
-        let amount_replied = stats.count.unwrap_or_default().to_string();
+        let stats_keyword = reply.keywords.first().unwrap();
+        query!(
+            "INSERT INTO auto_reply_usage (guild_id, day, keyword, user_id, count) VALUES ($1, $2, $3, $4, 1)
+             ON CONFLICT (guild_id, keyword, user_id, day) DO UPDATE SET count = auto_reply_usage.count + 1",
+            guild.get() as i64,
+            today(),
+            stats_keyword,
+            new_message.author.id.get() as i64
+        )
+        .execute(&data.database)
+        .await?;
 
-        let user = reply.user.to_user(ctx.http()).await?;
-        let desc = reply
-            .description
-            .replace("{user}", &user.to_string())
-            .replace("{replies}", &amount_replied);
-        let mut m = CreateMessage::new();
-        // embeds can't ping
-        if reply.ping {
-            m = m.content(user.mention().to_string());
-        }
-        let message = m.reference_message(new_message).embed(
-            CreateEmbed::new()
-                .title(&reply.title)
-                .description(desc)
-                .colour(reply.colour)
-                .author(
-                    CreateEmbedAuthor::new(&user.name)
-                        .icon_url(user.avatar_url().unwrap_or(user.default_avatar_url())),
-                ),
-        );
+        let trigger = ReplyTrigger {
+            author: &new_message.author,
+            channel: new_message.channel_id,
+            guild_id: guild,
+            guild: guild_name(ctx, new_message),
+            keyword,
+            captures,
+        };
+        let m = CreateMessage::new().reference_message(new_message);
+        let message = render_auto_reply(ctx.http(), &data.database, reply, trigger, m).await?;
         new_message
             .channel_id
             .send_message(&ctx.http, message)
@@ -182,9 +205,95 @@ async fn auto_reply(
     Ok(())
 }
 
+fn guild_name(ctx: &Context, message: &Message) -> String {
+    message
+        .guild_id
+        .and_then(|guild| guild.name(&ctx.cache))
+        .unwrap_or_default()
+}
+
+/// What triggered an auto reply, fills in the template variables
+pub(crate) struct ReplyTrigger<'a> {
+    pub(crate) author: &'a User,
+    pub(crate) channel: ChannelId,
+    /// the usage counts are per guild
+    pub(crate) guild_id: GuildId,
+    pub(crate) guild: String,
+    pub(crate) keyword: &'a str,
+    /// regex groups from [AutoReply::find_match]
+    pub(crate) captures: HashMap<String, String>,
+}
+
+/// Fill in the auto reply's placeholders and add it to `m`
+pub(crate) async fn render_auto_reply<T: EasyEmbed>(
+    http: &Http,
+    database: &PgPool,
+    reply: &AutoReply,
+    trigger: ReplyTrigger<'_>,
+    mut m: T,
+) -> anyhow::Result<T> {
+    let stats_keyword = reply
+        .keywords
+        .first()
+        .context("auto reply without keywords")?;
+    let stats = query!(
+        "SELECT SUM(count)::bigint AS count FROM auto_reply_usage WHERE guild_id = $1 AND keyword ILIKE '%' || $2 || '%'",
+        trigger.guild_id.get() as i64,
+        stats_keyword
+    )
+    .fetch_one(database)
+    .await?;
+    let author_count = query!(
+        "SELECT SUM(count)::bigint AS count FROM auto_reply_usage WHERE guild_id = $1 AND keyword = $2 AND user_id = $3",
+        trigger.guild_id.get() as i64,
+        stats_keyword,
+        trigger.author.id.get() as i64
+    )
+    .fetch_one(database)
+    .await?
+    .count
+    .unwrap_or_default();
+
+    let user = reply.user.to_user(http).await?;
+    let mut variables = trigger.captures;
+    variables.extend([
+        ("user".to_string(), user.mention().to_string()),
+        (
+            "replies".to_string(),
+            stats.count.unwrap_or_default().to_string(),
+        ),
+        ("author".to_string(), trigger.author.mention().to_string()),
+        ("author_count".to_string(), author_count.to_string()),
+        ("channel".to_string(), trigger.channel.mention().to_string()),
+        ("guild".to_string(), trigger.guild),
+        ("keyword".to_string(), trigger.keyword.to_string()),
+        ("date".to_string(), today().format(DATE_FORMAT).to_string()),
+    ]);
+    // validated when the reply was loaded, send it as is if that somehow changed
+    let render = |text: &str| {
+        Template::parse(text).map_or_else(|_| text.to_string(), |t| t.render(&variables))
+    };
+
+    // embeds can't ping
+    if reply.ping {
+        m = m.content(user.mention().to_string());
+    }
+    Ok(m.easy_embed(
+        CreateEmbed::new()
+            .title(render(&reply.title))
+            .description(render(&reply.description))
+            .colour(reply.colour)
+            .author(
+                CreateEmbedAuthor::new(&user.name)
+                    .icon_url(user.avatar_url().unwrap_or(user.default_avatar_url())),
+            ),
+    ))
+}
+
 async fn auto_react(
     ctx: &Context,
-    data: &Data,
+    guild: GuildId,
+    settings: &GuildSettings,
     new_message: &Message,
     content: &str,
 ) -> anyhow::Result<()> {
@@ -192,66 +301,46 @@ async fn auto_react(
         .find_iter(content)
         .map(|mat| mat.as_str())
         .collect_vec();
-    for (keyword, reaction) in &data.auto_reactions {
-        if words.contains(&keyword.as_str()) {
-            new_message.react(&ctx.http, reaction.clone()).await?;
+    for reaction in &settings.auto_reactions {
+        if !words.contains(&reaction.keyword.as_str()) {
+            continue;
+        }
+        let rule = Rule::Reaction(guild, reaction.keyword.clone());
+        if may_fire(rule, &reaction.scope, new_message) {
+            new_message.react(&ctx.http, reaction.emoji.clone()).await?;
         }
     }
     Ok(())
 }
 
-async fn celery_fact(ctx: &Context, data: &Data, channel: ChannelId) -> anyhow::Result<()> {
-    if let Some(config) = data.celery.get(&channel) {
-        // saturating subtraction
-        let previous_value =
-            config
-                .counter
-                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val| {
-                    if val == 0 { Some(0) } else { Some(val - 1) }
-                });
-        // not 0 now, still in cooldown
-        if previous_value.unwrap() > 1 {
-            return Ok(());
-        }
-        // unlucky
-        if !random_bool(config.chance) {
-            return Ok(());
-        }
-        // other thread was faster
-        let lock = config.mutex.try_lock();
-        if lock.is_err() {
-            return Ok(());
-        }
-        // reset cooldown and check if we waited so long another thread is done
-        if config
-            .counter
-            .compare_exchange(0, config.cooldown, Ordering::SeqCst, Ordering::SeqCst)
-            .is_err()
-        {
-            return Ok(());
-        }
-
-        let payload =
-            json!({"model": "deepseek-v3.1:671b", "prompt": config.prompt, "stream": false});
-
-        let response = HTTP_CLIENT
-            .post("https://ollama.com/api/generate")
-            .bearer_auth(&data.ollama_token)
-            .json(&payload)
-            .send()
-            .await?;
-
-        let json: json::Value = response.json().await?;
-
-        let fact = json["response"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Bad API response: {}", json))?
-            .to_string();
-        channel.say(&ctx.http, fact).await?;
-        drop(lock);
+/// Check where the rule may fire and start its cooldowns if it does
+fn may_fire(rule: Rule, scope: &RuleScope, message: &Message) -> bool {
+    let roles = message.member.as_ref().map_or(&[][..], |m| &m.roles);
+    if !scope.applies(message.channel_id, roles) {
+        return false;
     }
 
-    Ok(())
+    let now = Instant::now();
+    let cooldowns = [
+        (scope.channel_cooldown, message.channel_id.get()),
+        (scope.user_cooldown, message.author.id.get()),
+    ];
+    let cooling_down = cooldowns.iter().any(|&(cooldown, id)| {
+        cooldown.is_some_and(|secs| {
+            COOLDOWNS
+                .get(&(rule.clone(), id))
+                .is_some_and(|last| now - last < Duration::from_secs(secs.into()))
+        })
+    });
+    if cooling_down {
+        return false;
+    }
+    for (cooldown, id) in cooldowns {
+        if cooldown.is_some() {
+            COOLDOWNS.insert((rule.clone(), id), now);
+        }
+    }
+    true
 }
 
 pub(crate) async fn on_error(poise_error: poise::FrameworkError<'_, Data, anyhow::Error>) {
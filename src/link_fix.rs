@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use itertools::Itertools;
+use mini_moka::sync::Cache;
+use poise::serenity_prelude::{
+    ChannelId, Context, CreateAllowedMentions, CreateMessage, CreateWebhook, EditMessage,
+    ExecuteWebhook, Message, Webhook,
+};
+use regex::Regex;
+use reqwest::redirect::Policy;
+use reqwest::{Client as ReqwestClient, Url};
+use sqlx::query;
+use tracing::warn;
+
+use crate::Data;
+use crate::config::{ALL_DOMAINS, LinkFix, compile_pattern};
+use crate::constants::{ONE_DAY, USER_AGENT};
+use crate::guild_settings::GuildSettings;
+
+const WEBHOOK_NAME: &str = "WoBot link fixes";
+/// Hosts that only redirect to the real link
+const SHORT_LINK_HOSTS: [&str; 9] = [
+    "t.co",
+    "bit.ly",
+    "tinyurl.com",
+    "goo.gl",
+    "ow.ly",
+    "buff.ly",
+    "is.gd",
+    "vm.tiktok.com",
+    "amzn.to",
+];
+const EXPAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// Redirects followed when expanding a short link
+const MAX_REDIRECTS: usize = 5;
+
+/// reqwest only sets the redirect policy per client, [HTTP_CLIENT](crate::constants::HTTP_CLIENT)
+/// has to keep following redirects anywhere for the other commands
+static EXPAND_CLIENT: LazyLock<ReqwestClient> = LazyLock::new(|| {
+    ReqwestClient::builder()
+        .timeout(EXPAND_TIMEOUT)
+        .user_agent(USER_AGENT)
+        .redirect(Policy::custom(|attempt| {
+            let same_host = attempt
+                .previous()
+                .last()
+                .is_some_and(|previous| previous.host() == attempt.url().host());
+            if attempt.previous().len() > MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if same_host || is_public(attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        }))
+        .build()
+        .expect("HTTP client")
+});
+
+/// Users can post any short link, its redirects must not make the bot request internal hosts.
+/// Names are only checked for localhost, they aren't resolved
+fn is_public(url: &Url) -> bool {
+    let is_public_v4 = |ip: Ipv4Addr| {
+        !(ip.is_private()
+            || ip.is_loopback()
+            || ip.is_link_local()
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+            || ip.is_documentation())
+    };
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => is_public_v4(ip),
+        Ok(IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+        Err(_) => host != "localhost" && !host.ends_with(".localhost"),
+    }
+}
+
+/// Links in angle brackets don't embed, so they are skipped. `|` ends a link for spoilers
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<?https?://[^\s<>|]+").unwrap());
+/// Punctuation and markdown that ends a sentence rather than the link
+const TRAILING: &[char] = &['.', ',', ':', ';', '!', '?', '*', '_', '~', '\'', '"'];
+
+static WEBHOOK_CACHE: LazyLock<Cache<ChannelId, Webhook>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(100)
+        .time_to_idle(ONE_DAY)
+        .build()
+});
+
+/// Apply the fix for all domains and the one for the url's domain, returns whether something changed
+pub(crate) fn fix_link(link_fixes: &HashMap<String, LinkFix>, url: &mut Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.strip_prefix("www.").unwrap_or(host).to_string();
+    let original = url.clone();
+    for fix in [ALL_DOMAINS, &host]
+        .into_iter()
+        .filter_map(|domain| link_fixes.get(domain))
+    {
+        apply_fix(fix, url);
+    }
+    *url != original
+}
+
+fn apply_fix(fix: &LinkFix, url: &mut Url) {
+    remove_tracking(&fix.tracking, url);
+    for rewrite in &fix.paths {
+        let regex = match compile_pattern(&rewrite.pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                warn!(pattern = rewrite.pattern, error = ?e, "Invalid link fix path");
+                continue;
+            }
+        };
+        let path = regex.replace(url.path(), &rewrite.replacement).into_owned();
+        url.set_path(&path);
+    }
+    if let Some(host) = &fix.host
+        && let Err(e) = url.set_host(Some(host))
+    {
+        warn!(host, error = ?e, "Invalid link fix host");
+    }
+}
+
+/// Remove matching query parameters, the query is only re-encoded if one was removed
+fn remove_tracking(tracking: &[String], url: &mut Url) {
+    let pairs = url.query_pairs().into_owned().collect_vec();
+    let kept = pairs
+        .iter()
+        .filter(|(key, _)| !tracking.iter().any(|pattern| wildcard_match(pattern, key)))
+        .collect_vec();
+    if kept.len() == pairs.len() {
+        return;
+    }
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.collect_vec();
+    let Some(last) = parts.pop() else {
+        // no wildcard
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Follow the redirects of link shorteners, the original url if that fails
+pub(crate) async fn expand_short_link(url: Url) -> Url {
+    let is_short = url
+        .host_str()
+        .is_some_and(|host| SHORT_LINK_HOSTS.contains(&host.strip_prefix("www.").unwrap_or(host)));
+    if !is_short {
+        return url;
+    }
+    match resolve_redirects(&url).await {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            warn!(%url, error = ?e, "Failed to expand short link");
+            url
+        }
+    }
+}
+
+/// Where the redirects end, at most [MAX_REDIRECTS] are followed and none to other internal hosts
+async fn resolve_redirects(url: &Url) -> reqwest::Result<Url> {
+    // the status doesn't matter, some sites refuse HEAD requests at the final url
+    let response = EXPAND_CLIENT.head(url.clone()).send().await?;
+    Ok(response.url().clone())
+}
+
+/// Strip what follows a link in text, closing parentheses only if they weren't opened in the link
+fn trim_link(link: &str) -> &str {
+    let mut link = link;
+    loop {
+        let trimmed = link.trim_end_matches(TRAILING);
+        link = match trimmed.strip_suffix(')') {
+            Some(inner) if trimmed.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => return trimmed,
+        };
+    }
+}
+
+/// Fixed versions of the links in the message that needed fixing, with whether they were in a spoiler
+async fn fixed_links(link_fixes: &HashMap<String, LinkFix>, content: &str) -> Vec<(Url, bool)> {
+    let links = URL_REGEX
+        .find_iter(content)
+        .filter(|m| !m.as_str().starts_with('<'))
+        .filter_map(|m| {
+            let spoiler = content[..m.start()].matches("||").count() % 2 == 1;
+            Some((Url::parse(trim_link(m.as_str())).ok()?, spoiler))
+        })
+        .unique()
+        .collect_vec();
+    let mut fixed = Vec::new();
+    for (url, spoiler) in links {
+        let mut url = expand_short_link(url).await;
+        if fix_link(link_fixes, &mut url) {
+            fixed.push((url, spoiler));
+        }
+    }
+    fixed.into_iter().unique().collect()
+}
+
+/// Repost the message's links with the guild's fixes applied
+pub(crate) async fn auto_fix_links(
+    ctx: &Context,
+    data: &Data,
+    settings: &GuildSettings,
+    message: &Message,
+) -> anyhow::Result<()> {
+    if !settings.auto_link_fix || settings.link_fix_opt_outs.contains(&message.channel_id) {
+        return Ok(());
+    }
+    if !URL_REGEX.is_match(&message.content) {
+        return Ok(());
+    }
+    let opted_out = query!(
+        "SELECT EXISTS(SELECT 1 FROM link_fix_user_opt_outs WHERE user_id = $1) AS \"opted_out!\"",
+        message.author.id.get() as i64
+    )
+    .fetch_one(&data.database)
+    .await?
+    .opted_out;
+    if opted_out {
+        return Ok(());
+    }
+    let links = fixed_links(&settings.link_fixes, &message.content).await;
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let content = links
+        .iter()
+        .map(|(url, spoiler)| match spoiler {
+            true => format!("||{url}||"),
+            false => url.to_string(),
+        })
+        .join("\n");
+    if settings.link_fix_webhook {
+        repost_as_author(ctx, message, content).await?;
+    } else {
+        let reply = CreateMessage::new()
+            .content(content)
+            .reference_message(message)
+            .allowed_mentions(CreateAllowedMentions::new());
+        message.channel_id.send_message(ctx, reply).await?;
+    }
+    // needs Manage Messages, the fixed links are posted anyway
+    let suppress = EditMessage::new().suppress_embeds(true);
+    if let Err(e) = message.clone().edit(ctx, suppress).await {
+        warn!(error = ?e, channel = ?message.channel_id, "Failed to suppress embeds");
+    }
+    Ok(())
+}
+
+/// Send through a webhook with the author's name and avatar
+async fn repost_as_author(ctx: &Context, message: &Message, content: String) -> anyhow::Result<()> {
+    // webhooks belong to the parent channel of a thread
+    let thread_parent = message.guild_id.and_then(|guild| {
+        let guild = ctx.cache.guild(guild)?;
+        let thread = guild.threads.iter().find(|t| t.id == message.channel_id)?;
+        thread.parent_id
+    });
+    let channel = thread_parent.unwrap_or(message.channel_id);
+
+    let webhook = match WEBHOOK_CACHE.get(&channel) {
+        Some(webhook) => webhook,
+        None => {
+            let existing = channel
+                .webhooks(ctx)
+                .await?
+                .into_iter()
+                .find(|w| w.name.as_deref() == Some(WEBHOOK_NAME) && w.token.is_some());
+            let webhook = match existing {
+                Some(webhook) => webhook,
+                None => {
+                    channel
+                        .create_webhook(ctx, CreateWebhook::new(WEBHOOK_NAME))
+                        .await?
+                }
+            };
+            WEBHOOK_CACHE.insert(channel, webhook.clone());
+            webhook
+        }
+    };
+
+    let name = message
+        .member
+        .as_ref()
+        .and_then(|m| m.nick.clone())
+        .unwrap_or_else(|| message.author.display_name().to_string());
+    let mut execute = ExecuteWebhook::new()
+        .content(content)
+        .username(name)
+        .avatar_url(message.author.face())
+        .allowed_mentions(CreateAllowedMentions::new());
+    if thread_parent.is_some() {
+        execute = execute.in_thread(message.channel_id);
+    }
+    webhook.execute(ctx, false, execute).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PathRewrite;
+    use crate::test_utils::stand_in_server_with;
+
+    #[tokio::test]
+    async fn test_fixed_links() {
+        let link_fixes = HashMap::from([(
+            "reddit.com".to_string(),
+            LinkFix {
+                host: Some("old.reddit.com".to_string()),
+                ..Default::default()
+            },
+        )]);
+        let content = "look https://www.reddit.com/r/rust and <https://reddit.com/r/hidden> \
+            or https://example.com https://www.reddit.com/r/rust";
+        let links = fixed_links(&link_fixes, content).await;
+        assert_eq!(
+            links,
+            vec![(Url::parse("https://old.reddit.com/r/rust").unwrap(), false)]
+        );
+
+        let content = "||https://reddit.com/r/spoiler|| (see https://reddit.com/wiki/Rust_(language)). \
+            **https://reddit.com/r/bold**";
+        let links = fixed_links(&link_fixes, content).await;
+        assert_eq!(
+            links,
+            vec![
+                (
+                    Url::parse("https://old.reddit.com/r/spoiler").unwrap(),
+                    true
+                ),
+                (
+                    Url::parse("https://old.reddit.com/wiki/Rust_(language)").unwrap(),
+                    false
+                ),
+                (Url::parse("https://old.reddit.com/r/bold").unwrap(), false),
+            ]
+        );
+    }
+
+    fn fix(url: &str) -> String {
+        let link_fixes = HashMap::from([
+            (
+                ALL_DOMAINS.to_string(),
+                LinkFix {
+                    tracking: vec!["utm_*".to_string(), "fbclid".to_string()],
+                    ..Default::default()
+                },
+            ),
+            (
+                "instagram.com".to_string(),
+                LinkFix {
+                    tracking: vec!["igsh".to_string(), "img_index".to_string()],
+                    ..Default::default()
+                },
+            ),
+            (
+                "m.wikipedia.org".to_string(),
+                LinkFix {
+                    host: Some("en.wikipedia.org".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "open.spotify.com".to_string(),
+                LinkFix {
+                    paths: vec![PathRewrite {
+                        pattern: "^/intl-[a-z]+/".to_string(),
+                        replacement: "/".to_string(),
+                    }],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let mut url = Url::parse(url).unwrap();
+        fix_link(&link_fixes, &mut url);
+        url.to_string()
+    }
+
+    #[test]
+    fn test_fix_link() {
+        assert_eq!(
+            fix("https://www.instagram.com/p/1/?igsh=abc&img_index=2&utm_source=x"),
+            "https://www.instagram.com/p/1/"
+        );
+        assert_eq!(
+            fix("https://example.com/search?q=a%26b%20c&utm_medium=social&fbclid=1"),
+            "https://example.com/search?q=a%26b+c"
+        );
+        // untouched queries keep their encoding
+        assert_eq!(
+            fix("https://example.com/search?q=a%20b&utm=1"),
+            "https://example.com/search?q=a%20b&utm=1"
+        );
+        assert_eq!(
+            fix("https://open.spotify.com/intl-de/track/42?si=1"),
+            "https://open.spotify.com/track/42?si=1"
+        );
+        assert_eq!(
+            fix("https://m.wikipedia.org/wiki/Celery"),
+            "https://en.wikipedia.org/wiki/Celery"
+        );
+    }
+
+    /// `/n` redirects to `/n-1` until `/0`
+    fn redirect_chain(request: &str) -> String {
+        let hops: u32 = request
+            .split_whitespace()
+            .nth(1)
+            .and_then(|path| path.trim_start_matches('/').parse().ok())
+            .unwrap_or_default();
+        if hops == 0 {
+            return "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+        }
+        format!(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            hops - 1
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_redirects() {
+        let base = stand_in_server_with(redirect_chain).await;
+        let url = Url::parse(&format!("{base}/3")).unwrap();
+        let expanded = resolve_redirects(&url).await.unwrap();
+        assert_eq!(expanded.path(), "/0");
+
+        let too_far = Url::parse(&format!("{base}/{}", MAX_REDIRECTS + 1)).unwrap();
+        assert!(resolve_redirects(&too_far).await.is_err());
+        // only shorteners are expanded
+        assert_eq!(expand_short_link(url.clone()).await, url);
+
+        // a redirect to another internal host isn't followed
+        let base = stand_in_server_with(|_| {
+            "HTTP/1.1 302 Found\r\nLocation: http://localhost:1/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+        .await;
+        let url = Url::parse(&format!("{base}/inward")).unwrap();
+        assert_eq!(resolve_redirects(&url).await.unwrap(), url);
+    }
+
+    #[test]
+    fn test_is_public() {
+        let public = |url: &str| is_public(&Url::parse(url).unwrap());
+        assert!(public("https://www.youtube.com/watch"));
+        assert!(public("http://93.184.215.14/"));
+        assert!(!public("http://localhost:8080/"));
+        assert!(!public("http://db.localhost/"));
+        assert!(!public("http://127.0.0.1/"));
+        assert!(!public("http://10.0.0.1/"));
+        assert!(!public("http://192.168.1.1/"));
+        assert!(!public("http://169.254.169.254/latest/meta-data"));
+        assert!(!public("http://[::1]/"));
+        assert!(!public("http://[fd00::1]/"));
+        assert!(!public("http://[::ffff:127.0.0.1]/"));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("utm_*", "utm_source"));
+        assert!(wildcard_match("utm_*", "utm_"));
+        assert!(!wildcard_match("utm_*", "xutm_source"));
+        assert!(wildcard_match("*_id", "share_id"));
+        assert!(wildcard_match("a*b*c", "abbc"));
+        assert!(!wildcard_match("a*b*c", "ac"));
+        assert!(!wildcard_match("ab*ba", "aba"));
+        assert!(wildcard_match("igsh", "igsh"));
+        assert!(!wildcard_match("igsh", "igshid"));
+    }
+}
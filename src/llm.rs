@@ -0,0 +1,239 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use mini_moka::sync::Cache;
+use poise::serenity_prelude::UserId;
+use poise::serenity_prelude::json::{Value, json};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::constants::{HTTP_CLIENT, ONE_HOUR};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ApiStyle {
+    /// `/api/chat` of Ollama
+    #[default]
+    Ollama,
+    /// `/chat/completions` of OpenAI compatible servers, the base url usually ends with `/v1`
+    OpenAi,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct LlmConfig {
+    pub(crate) base_url: String,
+    pub(crate) model: String,
+    pub(crate) api: ApiStyle,
+    /// seconds until a request is given up
+    pub(crate) timeout: u64,
+    /// questions per user and hour for `/ask`
+    pub(crate) user_limit: u32,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        LlmConfig {
+            base_url: "https://ollama.com".to_string(),
+            model: "deepseek-v3.1:671b".to_string(),
+            api: ApiStyle::Ollama,
+            timeout: 60,
+            user_limit: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+impl From<&str> for Role {
+    fn from(value: &str) -> Self {
+        match value {
+            "system" => Role::System,
+            "assistant" => Role::Assistant,
+            _ => Role::User,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub(crate) struct ChatMessage {
+    pub(crate) role: Role,
+    pub(crate) content: String,
+}
+
+impl ChatMessage {
+    pub(crate) fn new(role: Role, content: impl Into<String>) -> Self {
+        ChatMessage {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+static REQUESTS_PER_USER: LazyLock<Cache<UserId, Arc<AtomicU32>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_live(ONE_HOUR)
+        .build()
+});
+/// held while a counter is looked up so a user's first requests share one counter
+static NEW_COUNTER: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Default)]
+struct Backoff {
+    failures: u32,
+    until: Option<Instant>,
+}
+
+/// Talks to the configured LLM server, failures are logged and pause requests for a while
+#[derive(Debug)]
+pub(crate) struct LlmClient {
+    config: LlmConfig,
+    token: Option<String>,
+    backoff: Mutex<Backoff>,
+}
+
+impl LlmClient {
+    pub(crate) fn new(config: LlmConfig, token: Option<String>) -> Self {
+        LlmClient {
+            config,
+            token: token.filter(|t| !t.is_empty()),
+            backoff: Mutex::default(),
+        }
+    }
+
+    /// Count a request of the user, false if they used up their hourly limit
+    pub(crate) fn allow_user(&self, user: UserId) -> bool {
+        // the counter expires an hour after the first request
+        let counter = {
+            let _guard = NEW_COUNTER.lock().expect("NEW_COUNTER");
+            REQUESTS_PER_USER.get(&user).unwrap_or_else(|| {
+                let counter = Arc::new(AtomicU32::new(0));
+                REQUESTS_PER_USER.insert(user, counter.clone());
+                counter
+            })
+        };
+        counter.fetch_add(1, Ordering::SeqCst) < self.config.user_limit
+    }
+
+    /// Answer a single prompt
+    pub(crate) async fn generate(&self, prompt: &str) -> anyhow::Result<String> {
+        self.chat(&[ChatMessage::new(Role::User, prompt)]).await
+    }
+
+    /// Answer the last message of the conversation, errors while backing off
+    pub(crate) async fn chat(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        if let Some(until) = self.backoff.lock().expect("backoff").until
+            && Instant::now() < until
+        {
+            bail!("LLM is unavailable after failed requests");
+        }
+
+        let result = self.request(messages).await;
+        let mut backoff = self.backoff.lock().expect("backoff");
+        match &result {
+            Ok(_) => *backoff = Backoff::default(),
+            Err(e) => {
+                let delay = MIN_BACKOFF
+                    .saturating_mul(2u32.saturating_pow(backoff.failures))
+                    .min(MAX_BACKOFF);
+                backoff.failures += 1;
+                backoff.until = Some(Instant::now() + delay);
+                warn!(error = ?e, retry_in = ?delay, "LLM request failed");
+            }
+        }
+        result
+    }
+
+    async fn request(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        let base_url = self.config.base_url.trim_end_matches('/');
+        let url = match self.config.api {
+            ApiStyle::Ollama => format!("{base_url}/api/chat"),
+            ApiStyle::OpenAi => format!("{base_url}/chat/completions"),
+        };
+        let payload = json!({"model": self.config.model, "messages": messages, "stream": false});
+
+        let mut request = HTTP_CLIENT
+            .post(url)
+            .timeout(Duration::from_secs(self.config.timeout))
+            .json(&payload);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let json: Value = request.send().await?.error_for_status()?.json().await?;
+
+        let content = match self.config.api {
+            ApiStyle::Ollama => &json["message"]["content"],
+            ApiStyle::OpenAi => &json["choices"][0]["message"]["content"],
+        };
+        content
+            .as_str()
+            .map(|s| s.trim().to_string())
+            .ok_or_else(|| anyhow!("Bad API response: {}", json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::stand_in_server;
+
+    fn client(base_url: String, api: ApiStyle) -> LlmClient {
+        let config = LlmConfig {
+            base_url,
+            api,
+            timeout: 5,
+            ..Default::default()
+        };
+        LlmClient::new(config, Some("token".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_ollama() {
+        let url =
+            stand_in_server(r#"{"message": {"role": "assistant", "content": " Celery! "}}"#).await;
+        let answer = client(url, ApiStyle::Ollama).generate("fact").await;
+        assert_eq!(answer.unwrap(), "Celery!");
+    }
+
+    #[tokio::test]
+    async fn test_openai() {
+        let url = stand_in_server(
+            r#"{"choices": [{"message": {"role": "assistant", "content": "Hi"}}]}"#,
+        )
+        .await;
+        let answer = client(url, ApiStyle::OpenAi).generate("hello").await;
+        assert_eq!(answer.unwrap(), "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_backoff() {
+        let url = stand_in_server(r#"{"unexpected": true}"#).await;
+        let client = client(url, ApiStyle::Ollama);
+        assert!(client.generate("fact").await.is_err());
+        assert!(client.backoff.lock().unwrap().until.is_some());
+        assert!(client.generate("fact").await.is_err());
+        assert_eq!(client.backoff.lock().unwrap().failures, 1);
+    }
+}
@@ -3,110 +3,57 @@ extern crate core;
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::Debug;
-use std::fs::read_to_string;
-use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use std::{error::Error, fmt};
 
+#[cfg(feature = "activity")]
+use crate::activity::{
+    ActivityAggregator, activity_cache, close_voice_sessions, end_voice_sessions, flush_activity,
+    keep_voice_sessions, load_activity_opt_outs, load_tracked_guilds,
+};
+use crate::celery::CeleryState;
 use crate::check_birthday::check_birthdays;
+#[cfg(feature = "activity")]
+use crate::check_prunes::check_prunes;
 use crate::check_reminder::check_reminders;
 use crate::commands::*;
-#[cfg(feature = "activity")]
-use crate::constants::ONE_DAY;
+use crate::config::{Config, LiveConfig, Settings, watch_config};
+use crate::llm::LlmClient;
 use itertools::Itertools;
 #[cfg(feature = "activity")]
-use mini_moka::sync::{Cache, CacheBuilder};
+use mini_moka::sync::Cache;
 use poise::builtins::{register_globally, register_in_guild};
-use poise::serenity_prelude::{
-    ChannelId, ClientBuilder, Colour, GatewayIntents, GuildId, ReactionType, UserId,
-};
+use poise::serenity_prelude::{ChannelId, ClientBuilder, GatewayIntents};
+#[cfg(feature = "activity")]
+use poise::serenity_prelude::{GuildId, UserId};
 use poise::{EditTracker, Framework, PrefixFrameworkOptions};
-use serde::Deserialize;
 use songbird::serenity::SerenityInit;
 use sqlx::{PgPool, query};
-use tokio::sync::Mutex;
+use tokio::signal::ctrl_c;
+use tokio::signal::unix::{SignalKind, signal};
+#[cfg(feature = "activity")]
+use tracing::error;
 use tracing::info;
 
+#[cfg(feature = "activity")]
+mod activity;
+mod celery;
 mod check_birthday;
+#[cfg(feature = "activity")]
+mod check_prunes;
 mod check_reminder;
 mod commands;
+mod config;
 mod constants;
 mod easy_embed;
+mod guild_settings;
 mod handler;
-
-#[derive(Debug, Deserialize)]
-struct AutoReply {
-    keywords: Vec<String>,
-    user: UserId,
-    title: String,
-    description: String,
-    #[serde(default)]
-    ping: bool,
-    #[serde(default)]
-    /// colour as an integer
-    colour: Colour,
-    chance: Option<f64>,
-}
-
-#[derive(Debug, Deserialize)]
-struct LinkFix {
-    host: Option<String>,
-    tracking: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct CeleryConfig {
-    prompt: String,
-    chance: f64,
-    cooldown: u64,
-}
-
-#[derive(Debug)]
-struct Celery {
-    prompt: String,
-    chance: f64,
-    cooldown: u64,
-    counter: AtomicU64,
-    mutex: Mutex<()>,
-}
-
-fn convert(config: HashMap<ChannelId, CeleryConfig>) -> HashMap<ChannelId, Celery> {
-    config
-        .into_iter()
-        .map(|(k, v)| {
-            (
-                k,
-                Celery {
-                    prompt: v.prompt,
-                    chance: v.chance,
-                    cooldown: v.cooldown,
-                    counter: AtomicU64::new(0),
-                    mutex: Mutex::new(()),
-                },
-            )
-        })
-        .collect()
-}
-
-#[derive(Deserialize)]
-struct Config {
-    #[cfg(feature = "activity")]
-    #[serde(default)]
-    active_guilds: Vec<GuildId>,
-    #[serde(default)]
-    event_channel_per_guild: HashMap<GuildId, ChannelId>,
-    #[serde(default)]
-    link_fixes: HashMap<String, LinkFix>,
-    #[serde(default)]
-    auto_reactions: HashMap<String, ReactionType>,
-    #[serde(default)]
-    auto_replies: Vec<AutoReply>,
-    #[serde(default)]
-    entry_sounds: HashMap<UserId, String>,
-    #[serde(default)]
-    celery: HashMap<ChannelId, CeleryConfig>,
-}
+mod link_fix;
+mod llm;
+mod template;
+#[cfg(test)]
+mod test_utils;
 
 #[cfg(feature = "activity")]
 #[derive(Debug, Clone)]
@@ -118,18 +65,22 @@ pub(crate) struct Data {
     cat_api_token: String,
     dog_api_token: String,
     mensaplan_token: String,
-    ollama_token: String,
+    llm: LlmClient,
     database: PgPool,
-    /// cache used to debounce user activity to once per day
+    /// cache used to debounce user activity to once per day, only for guilds with tracking
+    #[cfg(feature = "activity")]
+    activity_per_guild: RwLock<HashMap<GuildId, Cache<UserId, CacheEntry>>>,
+    /// users who don't want their activity recorded
+    #[cfg(feature = "activity")]
+    activity_opt_outs: RwLock<HashSet<UserId>>,
     #[cfg(feature = "activity")]
-    activity_per_guild: HashMap<GuildId, Cache<UserId, CacheEntry>>,
-    event_channel_per_guild: HashMap<GuildId, ChannelId>,
-    link_fixes: HashMap<String, LinkFix>,
-    auto_reactions: Vec<(String, ReactionType)>,
-    auto_replies: Vec<AutoReply>,
-    entry_sounds: HashMap<UserId, String>,
-    celery: HashMap<ChannelId, Celery>,
+    activity: Arc<ActivityAggregator>,
+    config: LiveConfig,
     reaction_msgs: RwLock<HashSet<u64>>,
+    /// celery cooldowns per channel
+    celery_state: RwLock<HashMap<ChannelId, Arc<CeleryState>>>,
+    /// threads started with `/ask`
+    llm_threads: RwLock<HashSet<ChannelId>>,
 }
 
 /// error type for user actionable issues like an invalid argument
@@ -161,14 +112,14 @@ type Context<'a> = poise::Context<'a, Data, anyhow::Error>;
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let config_data = read_to_string("assets/config.hjson").unwrap_or_default();
-    let config: Config = deser_hjson::from_str(&config_data).expect("Failed to parse config");
+    let config = Config::load().expect("Failed to load config");
+    let watch = config.watch_config;
     #[cfg(feature = "activity")]
-    let activity = config
-        .active_guilds
-        .into_iter()
-        .map(|guild| (guild, CacheBuilder::new(500).time_to_live(ONE_DAY).build()))
-        .collect();
+    let active_guilds = config.active_guilds.clone();
+    // the old name of LLM_TOKEN still works
+    let llm_token = env::var("LLM_TOKEN").or_else(|_| env::var("OLLAMA_TOKEN"));
+    let llm = LlmClient::new(config.llm.clone(), llm_token.ok());
+    let live_config = LiveConfig::new(Settings::from(config));
 
     let pool = PgPool::connect(&env::var("DATABASE_URL").expect("DATABASE_URL required"))
         .await
@@ -178,6 +129,17 @@ async fn main() {
         .run(&pool)
         .await
         .expect("Migrations failed");
+    #[cfg(feature = "activity")]
+    let activity = load_tracked_guilds(&pool, &active_guilds)
+        .await
+        .expect("Failed to load activity tracking")
+        .into_iter()
+        .map(|guild| (guild, activity_cache()))
+        .collect();
+    #[cfg(feature = "activity")]
+    let aggregator = Arc::new(ActivityAggregator::default());
+    #[cfg(feature = "activity")]
+    let (shutdown_pool, shutdown_aggregator) = (pool.clone(), aggregator.clone());
 
     let framework = Framework::builder()
         .options(poise::FrameworkOptions {
@@ -209,33 +171,43 @@ async fn main() {
                     .fetch_all(&pool)
                     .await?;
                 info!("Loaded reaction messages");
+                let llm_threads = load_llm_threads(&pool).await?;
                 check_reminders(ctx.clone(), pool.clone());
-                check_birthdays(
-                    ctx.clone(),
-                    pool.clone(),
-                    config.event_channel_per_guild.clone(),
-                );
+                check_birthdays(ctx.clone(), pool.clone());
+                #[cfg(feature = "activity")]
+                let activity_opt_outs = load_activity_opt_outs(&pool).await?;
+                #[cfg(feature = "activity")]
+                {
+                    check_prunes(ctx.clone(), pool.clone());
+                    close_voice_sessions(&pool).await?;
+                    keep_voice_sessions(pool.clone());
+                    flush_activity(pool.clone(), aggregator.clone());
+                }
+                if watch {
+                    watch_config(ctx.clone(), live_config.clone());
+                }
                 info!("{} is connected!", ready.user.name);
                 Ok(Data {
                     cat_api_token: env::var("CAT_API_TOKEN").unwrap_or_default(),
                     dog_api_token: env::var("DOG_API_TOKEN").unwrap_or_default(),
                     mensaplan_token: env::var("MENSAPLAN_TOKEN").unwrap_or_default(),
-                    ollama_token: env::var("OLLAMA_TOKEN").unwrap_or_default(),
+                    llm,
                     database: pool,
                     #[cfg(feature = "activity")]
-                    activity_per_guild: activity,
-                    event_channel_per_guild: config.event_channel_per_guild,
-                    link_fixes: config.link_fixes,
-                    auto_reactions: config.auto_reactions.into_iter().collect_vec(),
-                    auto_replies: config.auto_replies,
-                    entry_sounds: config.entry_sounds,
-                    celery: convert(config.celery),
+                    activity_per_guild: RwLock::new(activity),
+                    #[cfg(feature = "activity")]
+                    activity_opt_outs: RwLock::new(activity_opt_outs.into_iter().collect()),
+                    #[cfg(feature = "activity")]
+                    activity: aggregator,
+                    config: live_config,
                     reaction_msgs: RwLock::new(
                         reaction_msgs
                             .into_iter()
                             .map(|f| f.message_id as u64)
                             .collect(),
                     ),
+                    celery_state: RwLock::default(),
+                    llm_threads: RwLock::new(llm_threads.into_iter().collect()),
                 })
             })
         })
@@ -248,5 +220,30 @@ async fn main() {
         .register_songbird()
         .await;
 
-    client.unwrap().start().await.unwrap();
+    let mut client = client.unwrap();
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down");
+        shard_manager.shutdown_all().await;
+    });
+    client.start().await.unwrap();
+
+    #[cfg(feature = "activity")]
+    if let Err(e) = shutdown_aggregator.flush(&shutdown_pool).await {
+        error!(error = ?e, "Failed flushing activity on shutdown");
+    }
+    #[cfg(feature = "activity")]
+    if let Err(e) = end_voice_sessions(&shutdown_pool).await {
+        error!(error = ?e, "Failed ending voice sessions on shutdown");
+    }
+}
+
+/// Ctrl+C or SIGTERM from `docker stop`
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
 }
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use rand::seq::IndexedRandom;
+
+/// Placeholders every auto reply can use, regex groups come on top
+pub(crate) const VARIABLES: [&str; 8] = [
+    "user",
+    "replies",
+    "author",
+    "author_count",
+    "channel",
+    "guild",
+    "keyword",
+    "date",
+];
+const ONE_OF: &str = "one of:";
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Variable(String),
+    OneOf(Vec<String>),
+}
+
+/// Text with `{variable}` and `{one of: a|b|c}` placeholders, `{{` and `}}` are literal braces
+#[derive(Debug, PartialEq)]
+pub(crate) struct Template(Vec<Part>);
+
+impl Template {
+    pub(crate) fn parse(text: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => literal.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => literal.push('}'),
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("`{{{placeholder}` is missing a closing `}}`"));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&placeholder)?);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template(parts))
+    }
+
+    pub(crate) fn variables(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|part| match part {
+            Part::Variable(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Fill in the variables, unset ones are left empty
+    pub(crate) fn render(&self, variables: &HashMap<String, String>) -> String {
+        let mut rng = rand::rng();
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Variable(name) => variables.get(name).map_or("", String::as_str),
+                Part::OneOf(options) => options.choose(&mut rng).map_or("", String::as_str),
+            })
+            .collect()
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part, String> {
+    if let Some(options) = placeholder.strip_prefix(ONE_OF) {
+        return Ok(Part::OneOf(
+            options.split('|').map(|o| o.trim().to_string()).collect(),
+        ));
+    }
+    let name = placeholder.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("`{{{placeholder}}}` is not a valid placeholder"));
+    }
+    Ok(Part::Variable(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse("Hi {author}, {{literal}} {one of: a|b}!").unwrap();
+        assert_eq!(template.variables().collect::<Vec<_>>(), vec!["author"]);
+        let variables = HashMap::from([("author".to_string(), "Bob".to_string())]);
+        let rendered = template.render(&variables);
+        assert!(rendered == "Hi Bob, {literal} a!" || rendered == "Hi Bob, {literal} b!");
+        assert_eq!(Template::parse("{missing}").unwrap().render(&variables), "");
+    }
+
+    #[test]
+    fn test_reject_invalid() {
+        assert!(Template::parse("{author").is_err());
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("{two words}").is_err());
+        assert!(Template::parse("lonely }").is_ok());
+    }
+}
@@ -0,0 +1,33 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serves every request with status 200 and `body` on a random local port, returns the base url
+pub(crate) async fn stand_in_server(body: &'static str) -> String {
+    stand_in_server_with(move |_| {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    })
+    .await
+}
+
+/// Like [stand_in_server], but the whole response is built from the request
+pub(crate) async fn stand_in_server_with(
+    respond: impl Fn(&str) -> String + Copy + Send + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                // the requests are small, one read gets the headers and body
+                let mut buffer = [0; 8192];
+                let read = stream.read(&mut buffer).await.unwrap_or_default();
+                let response = respond(&String::from_utf8_lossy(&buffer[..read]));
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    format!("http://{address}")
+}